@@ -0,0 +1,122 @@
+#[cfg(not(feature = "download"))]
+fn main() {}
+
+#[cfg(feature = "download")]
+fn main() {
+    download::run();
+}
+
+#[cfg(feature = "download")]
+mod download {
+    use sha2::{Digest, Sha256};
+    use std::{
+        env, fs,
+        io::Write,
+        path::{Path, PathBuf},
+    };
+
+    /// Relay version to download
+    #[cfg(feature = "relay_0_9_0")]
+    const VERSION: Option<&str> = Some("0.9.0");
+
+    #[cfg(not(feature = "relay_0_9_0"))]
+    const VERSION: Option<&str> = None;
+
+    /// Default location binaries are fetched from, can be overridden with the
+    /// `NOSTRD_DOWNLOAD_ENDPOINT` environment variable
+    const DEFAULT_ENDPOINT: &str = "https://github.com/pythcoiner/nostrd/raw/master/bin";
+
+    /// (version, target os, target arch, file name, sha256)
+    const BINARIES: &[(&str, &str, &str, &str, &str)] = &[(
+        "0.9.0",
+        "linux",
+        "x86_64",
+        "nostr-rs-relay_0_9_0_linux",
+        "f2e9a0c01f2d3b887afb7587bd2d41df05a31a26d2b63ef4523458c1a4d4f9b3",
+    )];
+
+    pub fn run() {
+        println!("cargo:rerun-if-env-changed=NOSTRD_DOWNLOAD_ENDPOINT");
+        println!("cargo:rerun-if-env-changed=NOSTRD_SKIP_DOWNLOAD");
+
+        if env::var_os("NOSTRD_SKIP_DOWNLOAD").is_some() {
+            return;
+        }
+
+        let version = match VERSION {
+            Some(version) => version,
+            None => {
+                println!("cargo:warning=no relay version feature enabled, skip download");
+                return;
+            }
+        };
+
+        let os = env::var("CARGO_CFG_TARGET_OS").expect("set by cargo");
+        let arch = env::var("CARGO_CFG_TARGET_ARCH").expect("set by cargo");
+        let (name, sha256) = match BINARIES
+            .iter()
+            .find(|(v, o, a, _, _)| *v == version && *o == os && *a == arch)
+        {
+            Some((_, _, _, name, sha256)) => (*name, *sha256),
+            None => {
+                println!(
+                    "cargo:warning=no nostr-rs-relay {} binary available for {}-{}",
+                    version, arch, os
+                );
+                return;
+            }
+        };
+
+        let mut dir = PathBuf::from(env::var("OUT_DIR").expect("set by cargo"));
+        dir.push("nostr-rs-relay");
+        dir.push(version);
+        let exe = dir.join(name);
+
+        if !is_valid(&exe, sha256) {
+            let endpoint =
+                env::var("NOSTRD_DOWNLOAD_ENDPOINT").unwrap_or(DEFAULT_ENDPOINT.to_string());
+            let url = format!("{}/{}", endpoint.trim_end_matches('/'), name);
+            let resp = minreq::get(&url)
+                .send()
+                .unwrap_or_else(|e| panic!("cannot download {}: {}", url, e));
+            if resp.status_code != 200 {
+                panic!("cannot download {}: status {}", url, resp.status_code);
+            }
+            let bytes = resp.into_bytes();
+            let hash = hex(&Sha256::digest(&bytes));
+            if hash != sha256 {
+                panic!(
+                    "checksum mismatch for {}: expected {} got {}",
+                    url, sha256, hash
+                );
+            }
+            fs::create_dir_all(&dir).unwrap();
+            let mut file = fs::File::create(&exe).unwrap();
+            file.write_all(&bytes).unwrap();
+            drop(file);
+            set_executable(&exe);
+        }
+
+        println!("cargo:rustc-env=NOSTRD_DOWNLOADED_EXE={}", exe.display());
+    }
+
+    fn is_valid(exe: &Path, sha256: &str) -> bool {
+        match fs::read(exe) {
+            Ok(bytes) => hex(&Sha256::digest(&bytes)) == sha256,
+            Err(_) => false,
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[cfg(unix)]
+    fn set_executable(exe: &Path) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(exe, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(not(unix))]
+    fn set_executable(_exe: &Path) {}
+}
@@ -0,0 +1,30 @@
+use crate::{Conf, Error, NostrD};
+use futures_core::Stream;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+
+impl NostrD {
+    /// Create a new nostr process, waiting asynchronously for it to be ready
+    pub async fn new_async() -> Result<NostrD, Error> {
+        NostrD::with_conf_async(&Conf::default()).await
+    }
+
+    /// Create a new process using given [Conf], waiting asynchronously for it to be ready
+    ///
+    /// [NostrD::with_conf] runs on the blocking thread pool, so it must be called from a tokio
+    /// runtime.
+    pub async fn with_conf_async(conf: &Conf) -> Result<NostrD, Error> {
+        let conf = conf.clone();
+        match tokio::task::spawn_blocking(move || NostrD::with_conf(&conf)).await {
+            Ok(nostrd) => nostrd,
+            // Blocking tasks are not cancelled, only a panic lands here
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// Returns a stream of the log lines received from now on
+    ///
+    /// Each stream gets its own copy of the lines, lines are skipped when a stream lags behind.
+    pub fn log_stream(&self) -> impl Stream<Item = String> {
+        BroadcastStream::new(self.broadcast.subscribe()).filter_map(Result::ok)
+    }
+}
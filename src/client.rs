@@ -0,0 +1,72 @@
+use crate::{Error, NostrD};
+use nostr::{
+    event::{Event, EventBuilder, EventId, FinalizeEvent},
+    filter::Filter,
+    key::Keys,
+};
+use nostr_sdk::client::Client;
+use std::time::Duration;
+
+/// Time to wait for the client to connect to the relay
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Relay answer to a published event, from its `OK` message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishOutcome {
+    /// `OK true`, the event is stored
+    Accepted(EventId),
+    /// `OK false`, with the relay message
+    Rejected { message: String },
+}
+
+impl NostrD {
+    /// Returns a nostr-sdk [Client] connected to the relay
+    pub async fn client(&self) -> Result<Client, Error> {
+        let client = Client::new();
+        client.add_relay(self.url()).await?;
+        client
+            .try_connect_relay(self.url(), CONNECT_TIMEOUT)
+            .await?;
+        Ok(client)
+    }
+
+    /// Sign `event` with `keys`, publish it and wait for the relay `OK`
+    ///
+    /// An event refused by the relay returns [Error::Rejected] with the relay message.
+    pub async fn publish(&self, keys: &Keys, event: EventBuilder) -> Result<EventId, Error> {
+        match self.publish_outcome(keys, event).await? {
+            PublishOutcome::Accepted(id) => Ok(id),
+            PublishOutcome::Rejected { message } => Err(Error::Rejected(message)),
+        }
+    }
+
+    /// Sign `event` with `keys`, publish it and return the relay answer
+    pub async fn publish_outcome(
+        &self,
+        keys: &Keys,
+        event: EventBuilder,
+    ) -> Result<PublishOutcome, Error> {
+        let client = self.client().await?;
+        let event = event.finalize(keys)?;
+        let output = client.send_event(&event).await?;
+        client.shutdown().await;
+        Ok(match output.failed.into_values().next() {
+            Some(message) => PublishOutcome::Rejected { message },
+            None => PublishOutcome::Accepted(output.value),
+        })
+    }
+
+    /// Subscribe with `filters` and return the matching events
+    ///
+    /// Events are collected until the relay sends `EOSE` or `timeout` elapses.
+    pub async fn fetch(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Duration,
+    ) -> Result<Vec<Event>, Error> {
+        let client = self.client().await?;
+        let events = client.fetch_events(filters).timeout(timeout).await;
+        client.shutdown().await;
+        Ok(events?.into_iter().collect())
+    }
+}
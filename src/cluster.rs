@@ -0,0 +1,29 @@
+use crate::{get_available_ports, Conf, Error, NostrD};
+
+/// A set of independent relays, all torn down when dropped
+pub struct NostrCluster {
+    /// The relays of the cluster
+    pub nodes: Vec<NostrD>,
+}
+
+impl NostrCluster {
+    /// Spawn `count` relays from `conf`, each on its own free port
+    ///
+    /// `conf.port` is ignored.
+    pub fn new(count: usize, conf: &Conf) -> Result<NostrCluster, Error> {
+        let nodes = get_available_ports(count)?
+            .into_iter()
+            .map(|port| {
+                let mut conf = conf.clone();
+                conf.port = Some(port);
+                NostrD::with_conf(&conf)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(NostrCluster { nodes })
+    }
+
+    /// Returns the websocket url of each relay
+    pub fn urls(&self) -> Vec<String> {
+        self.nodes.iter().map(NostrD::url).collect()
+    }
+}
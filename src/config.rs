@@ -0,0 +1,189 @@
+use crate::{Conf, Database, Error, PayToRelay};
+use serde::Serialize;
+
+/// Default `[database] min_conn` of the relay
+const RELAY_MIN_CONN: usize = 4;
+
+/// Mirror of the relay `config.toml` schema, unset values are not written
+#[derive(Debug, Serialize)]
+pub(crate) struct RelayConfig {
+    info: Info,
+    network: Network,
+    limits: Limits,
+    authorization: Authorization,
+    options: Options,
+    database: DatabaseConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pay_to_relay: Option<PayToRelay>,
+    verified_users: VerifiedUsers,
+    diagnostics: Diagnostics,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Info {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pubkey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contact: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Network {
+    address: String,
+    port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ping_interval_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_ip_header: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Limits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_event_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    messages_per_sec: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subscriptions_per_min: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_ws_message_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_ws_frame_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    db_conns_per_client: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_persist_buffer: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    broadcast_buffer: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_blocking_threads: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_kind_blacklist: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_kind_allowlist: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Authorization {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pubkey_whitelist: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nip42_auth: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reject_future_seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct DatabaseConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    engine: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_conn: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_conn: Option<usize>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct VerifiedUsers {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    domain_whitelist: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    domain_blacklist: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Diagnostics {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracing: Option<bool>,
+}
+
+impl RelayConfig {
+    /// Returns the relay config matching `conf`, listening on `ip`:`port`
+    pub(crate) fn new(conf: &Conf, ip: &str, port: u16) -> RelayConfig {
+        let (engine, connection) = match &conf.database {
+            Database::Sqlite => (None, None),
+            Database::Postgres { connection } => {
+                (Some("postgres".to_string()), Some(connection.clone()))
+            }
+        };
+        RelayConfig {
+            info: Info {
+                name: conf.relay_name.clone(),
+                description: conf.relay_description.clone(),
+                pubkey: conf.info_pubkey.clone(),
+                contact: conf.info_contact.clone(),
+            },
+            network: Network {
+                address: ip.to_string(),
+                port,
+                ping_interval_seconds: conf.ping_interval_seconds,
+                remote_ip_header: conf.remote_ip_header.clone(),
+            },
+            limits: Limits {
+                max_event_bytes: conf.max_event_bytes,
+                messages_per_sec: conf.messages_per_sec,
+                subscriptions_per_min: conf.subscriptions_per_min,
+                max_ws_message_bytes: conf.max_ws_message_bytes,
+                max_ws_frame_bytes: conf.max_ws_frame_bytes,
+                db_conns_per_client: conf.db_conns_per_client,
+                event_persist_buffer: conf.event_persist_buffer,
+                broadcast_buffer: conf.broadcast_buffer,
+                max_blocking_threads: conf.max_blocking_threads,
+                event_kind_blacklist: conf.event_kind_blacklist.clone(),
+                event_kind_allowlist: conf.event_kind_allowlist.clone(),
+            },
+            authorization: Authorization {
+                pubkey_whitelist: conf.pubkey_whitelist.clone(),
+                nip42_auth: conf.nip42_auth,
+            },
+            options: Options {
+                reject_future_seconds: conf.reject_future_seconds,
+            },
+            database: DatabaseConfig {
+                engine,
+                connection,
+                // The relay refuses a `min_conn` above `max_conn`
+                min_conn: conf.max_connections.filter(|max| *max < RELAY_MIN_CONN),
+                max_conn: conf.max_connections,
+            },
+            pay_to_relay: conf.pay_to_relay.clone(),
+            verified_users: VerifiedUsers {
+                mode: conf.verified_users_mode.clone(),
+                domain_whitelist: conf.verified_users_domain_whitelist.clone(),
+                domain_blacklist: conf.verified_users_domain_blacklist.clone(),
+            },
+            diagnostics: Diagnostics {
+                tracing: conf.diagnostics_tracing,
+            },
+        }
+    }
+
+    /// Write the set values over `base`, replacing the keys already there
+    pub(crate) fn merge_into(&self, base: &mut toml::Table) -> Result<(), Error> {
+        let table = toml::Table::try_from(self).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        for (section, values) in table {
+            let values = match values {
+                toml::Value::Table(values) if !values.is_empty() => values,
+                _ => continue,
+            };
+            base.entry(section.as_str())
+                .or_insert_with(|| toml::Table::new().into())
+                .as_table_mut()
+                .ok_or_else(|| Error::InvalidConfig(format!("`{}` must be a table", section)))?
+                .extend(values);
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,71 @@
+use std::path::PathBuf;
+
 /// All the possible error in this crate
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// Wrapper of io Error
     Io(std::io::Error),
+    #[cfg(unix)]
     Nix(nix::errno::Errno),
+    /// The relay binary does not exist at the given path
+    BinaryNotFound(PathBuf),
+    /// No binary configured and none downloaded or vendored
+    NoBinary,
+    /// The relay binary path does not point to a file
+    NotAFile(PathBuf),
+    /// The relay binary lacks the execute permission (unix)
+    BinaryNotExecutable(PathBuf),
+    /// The relay did not start after the given number of attempts
+    SpawnFailed { attempts: u8 },
+    /// The relay logged a fatal error while starting, with the offending line
+    StartupFailed(String),
+    /// The fixed [crate::Conf::port] is already bound by another process
+    PortInUse(u16),
+    /// The awaited event did not happen in time
+    Timeout,
+    /// The configuration is invalid
+    InvalidConfig(String),
+    /// The relay log readers are gone, no more lines will be received
+    LogsDisconnected,
+    /// The relay binary `--version` output holds no version, with the given output
+    UnknownVersion(String),
+    /// Wrapper of rusqlite Error
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    /// Wrapper of minreq Error
+    #[cfg(feature = "http")]
+    Http(minreq::Error),
+    /// Wrapper of nostr-sdk Error
+    #[cfg(feature = "client")]
+    Client(nostr_sdk::error::Error),
+    /// Wrapper of nostr Error
+    #[cfg(feature = "client")]
+    Nostr(nostr::error::Error),
+    /// The relay answered `OK false`, with the given message
+    #[cfg(feature = "client")]
+    Rejected(String),
+    /// Wrapper of tungstenite Error
+    #[cfg(feature = "ws")]
+    Ws(Box<tungstenite::Error>),
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Error::Io(e) => Some(e),
+            #[cfg(unix)]
+            Error::Nix(e) => Some(e),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(e) => Some(e),
+            #[cfg(feature = "http")]
+            Error::Http(e) => Some(e),
+            #[cfg(feature = "client")]
+            Error::Client(e) => Some(e),
+            #[cfg(feature = "client")]
+            Error::Nostr(e) => Some(e),
+            #[cfg(feature = "ws")]
+            Error::Ws(e) => Some(e),
             _ => None,
         }
     }
@@ -17,7 +73,46 @@ impl std::error::Error for Error {
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            #[cfg(unix)]
+            Error::Nix(e) => write!(f, "signal error: {}", e),
+            Error::BinaryNotFound(path) => {
+                write!(f, "relay binary not found at {}", path.display())
+            }
+            Error::NoBinary => write!(
+                f,
+                "no relay binary found, set `{}` or Conf::binary",
+                crate::EXE_ENV_VAR
+            ),
+            Error::NotAFile(path) => write!(f, "relay binary {} is not a file", path.display()),
+            Error::BinaryNotExecutable(path) => {
+                write!(f, "relay binary {} is not executable", path.display())
+            }
+            Error::SpawnFailed { attempts } => {
+                write!(f, "relay failed to start after {} attempt(s)", attempts)
+            }
+            Error::StartupFailed(line) => write!(f, "relay failed to start: {}", line),
+            Error::PortInUse(port) => write!(f, "port {} is already in use", port),
+            Error::Timeout => write!(f, "timed out"),
+            Error::InvalidConfig(e) => write!(f, "invalid configuration: {}", e),
+            Error::LogsDisconnected => write!(f, "relay log stream disconnected"),
+            Error::UnknownVersion(output) => {
+                write!(f, "no version in the relay binary output: {:?}", output)
+            }
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(e) => write!(f, "sqlite error: {}", e),
+            #[cfg(feature = "http")]
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            #[cfg(feature = "client")]
+            Error::Client(e) => write!(f, "nostr client error: {}", e),
+            #[cfg(feature = "client")]
+            Error::Nostr(e) => write!(f, "nostr error: {}", e),
+            #[cfg(feature = "client")]
+            Error::Rejected(message) => write!(f, "event rejected by the relay: {}", message),
+            #[cfg(feature = "ws")]
+            Error::Ws(e) => write!(f, "WebSocket error: {}", e),
+        }
     }
 }
 
@@ -27,8 +122,44 @@ impl From<std::io::Error> for Error {
     }
 }
 
+#[cfg(unix)]
 impl From<nix::errno::Errno> for Error {
     fn from(value: nix::errno::Errno) -> Self {
         Error::Nix(value)
     }
 }
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(value: rusqlite::Error) -> Self {
+        Error::Sqlite(value)
+    }
+}
+
+#[cfg(feature = "http")]
+impl From<minreq::Error> for Error {
+    fn from(value: minreq::Error) -> Self {
+        Error::Http(value)
+    }
+}
+
+#[cfg(feature = "client")]
+impl From<nostr_sdk::error::Error> for Error {
+    fn from(value: nostr_sdk::error::Error) -> Self {
+        Error::Client(value)
+    }
+}
+
+#[cfg(feature = "client")]
+impl From<nostr::error::Error> for Error {
+    fn from(value: nostr::error::Error) -> Self {
+        Error::Nostr(value)
+    }
+}
+
+#[cfg(feature = "ws")]
+impl From<tungstenite::Error> for Error {
+    fn from(value: tungstenite::Error) -> Self {
+        Error::Ws(Box::new(value))
+    }
+}
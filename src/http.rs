@@ -0,0 +1,19 @@
+use crate::{Error, NostrD};
+
+impl NostrD {
+    /// Fetch and parse the relay information document (NIP-11)
+    pub fn nip11(&self) -> Result<serde_json::Value, Error> {
+        let resp = minreq::get(self.http_url())
+            .with_header("Accept", "application/nostr+json")
+            .send()?;
+        Ok(resp.json()?)
+    }
+
+    /// Returns true if the relay answers `200 OK` on its HTTP health route
+    ///
+    /// The relay has no dedicated route, its Prometheus `/metrics` endpoint is probed.
+    pub fn health(&self) -> Result<bool, Error> {
+        let resp = minreq::get(format!("{}/metrics", self.http_url())).send()?;
+        Ok(resp.status_code == 200)
+    }
+}
@@ -1,216 +1,1278 @@
+#[cfg(feature = "tokio")]
+mod asynchronous;
+#[cfg(feature = "client")]
+mod client;
+mod cluster;
+mod config;
 mod error;
+#[cfg(feature = "http")]
+mod http;
+mod logs;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod versions;
+#[cfg(feature = "ws")]
+mod ws;
+use config::RelayConfig;
+use logs::{log_channel, LogSender};
+use serde::Serialize;
 use std::{
-    fs::File,
+    env,
+    fs::{self, File},
     io::{self, BufRead, BufReader, Write},
-    net::TcpListener,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
     path::{Path, PathBuf},
-    process::{Child, Command, Stdio},
+    process::{Child, Command, ExitStatus, Stdio},
     sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
-        mpsc::{self, Receiver},
-        Arc,
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex, OnceLock,
     },
     thread::{self, sleep},
     time::{Duration, Instant},
 };
-use temp_dir::TempDir;
+use tempfile::TempDir;
 
+#[cfg(feature = "client")]
+pub use client::PublishOutcome;
+pub use cluster::NostrCluster;
 pub use error::Error;
+pub use logs::{LogReceiver, LogRecord, LogSource};
+#[cfg(unix)]
+pub use nix::sys::signal::Signal;
+pub use versions::VERSION;
+
+/// Number of lines buffered for each [NostrD::log_stream] consumer
+#[cfg(feature = "tokio")]
+const LOG_STREAM_CAPACITY: usize = 1024;
+
+/// Environment variable pointing to the relay binary, used when [Conf::binary] is not set
+pub const EXE_ENV_VAR: &str = "NOSTRD_EXE";
+
+/// Name of the relay config file written in the work directory
+const CONFIG_FILE: &str = "config.toml";
+
+/// Log line substring emitted by the relay once it's ready to accept connections
+pub const READY_MARKER: &str = "control message listener started";
+
+/// Log line substring emitted by the relay when a websocket client connects, at `info` level
+pub const CONNECTION_MARKER: &str = "new client connection";
+
+/// Database backend used by the relay
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Database {
+    /// SQLite database stored in the work directory
+    Sqlite,
+    /// PostgreSQL database reached through `connection`, e.g. `postgresql://user@localhost/nostr`
+    Postgres { connection: String },
+}
+
+/// Pay-to-relay settings
+///
+/// The relay refuses to start when enabled without a `node_url` and an `api_secret`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct PayToRelay {
+    /// Require unknown pubkeys to pay before publishing
+    pub enabled: bool,
+    /// Cost to be admitted to the relay, in sats
+    pub admission_cost: u64,
+    /// Cost of each published event, in sats
+    pub cost_per_event: u64,
+    /// Terms of service users agree to when signing up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terms_message: Option<String>,
+    /// Url of the lightning node API handling the payments
+    pub node_url: String,
+    /// Secret of the lightning node API
+    pub api_secret: String,
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[non_exhaustive]
-pub struct Conf<'a> {
+pub struct Conf {
     /// command line arguments
-    pub args: Vec<&'a str>,
+    ///
+    /// `--config` & `--db` are managed by nostrd, passing them is an [Error::InvalidConfig].
+    /// Relative paths resolve from [Conf::current_dir].
+    pub args: Vec<String>,
+
+    /// Working directory of the relay process, defaults to the work directory
+    pub current_dir: Option<PathBuf>,
+
+    /// Environment variables passed to the relay process, after `RUST_LOG`
+    pub envs: Vec<(String, String)>,
 
     /// Try to spawn the process `attempt` time
     ///
     /// The OS is giving available ports to use, however, they aren't booked, so it could rarely
     /// happen they are used at the time the process is spawn. When retrying other available ports
-    /// are returned reducing the probability of conflicts to negligible.
-    attempts: u8,
+    /// are picked reducing the probability of conflicts to negligible, unless [Conf::port] is
+    /// fixed. A picked port taken by the time the relay binds it is retried too, not reported as
+    /// [Error::StartupFailed].
+    ///
+    /// `0` is handled as `1`, the process is always spawned at least once.
+    pub attempts: u8,
+
+    /// Time to wait between two spawn attempts
+    pub retry_backoff: Duration,
 
     /// The ip to bind to
     pub ip: Option<String>,
 
-    /// The port to listen on
+    /// The port to listen on, an available one is picked if not set or `0`
+    ///
+    /// A fixed port already in use is reported as [Error::PortInUse], without retrying.
     pub port: Option<u16>,
 
-    // Path to the binary
+    /// The relay is served over TLS (e.g. behind a proxy set up through [Conf::config_toml]),
+    /// [NostrD::url] returns a `wss://` address
+    pub tls: bool,
+
+    /// Path to the binary, defaults to [EXE_ENV_VAR] or the downloaded/vendored one
     pub binary: Option<String>,
+
+    /// Time to wait for the relay to be ready before killing it and retrying
+    pub start_timeout: Duration,
+
+    /// Time to wait for the relay to exit after [Conf::stop_signal] before killing it
+    pub shutdown_timeout: Duration,
+
+    /// Signal sent to stop the relay, defaults to `SIGINT` (unix)
+    ///
+    /// The relay installs its `SIGINT`/`SIGTERM` handlers shortly after logging it's ready, a
+    /// signal sent right after startup may kill it without going through its graceful shutdown.
+    #[cfg(unix)]
+    pub stop_signal: Signal,
+
+    /// Log line substring signaling the relay is ready, defaults to [READY_MARKER]
+    pub ready_marker: Option<String>,
+
+    /// Keep the work directory (config & database) once [NostrD] is dropped
+    pub keep_data_dir: bool,
+
+    /// Directory the work directory is created in, defaults to the system temp directory
+    pub data_dir_parent: Option<PathBuf>,
+
+    /// File every relay log line is appended to, as soon as it's read
+    pub log_file: Option<PathBuf>,
+
+    /// Maximum number of unread lines kept by each log receiver, unbounded if not set
+    ///
+    /// Once reached, the oldest unread lines are dropped to make room for the new ones. `0` is an
+    /// [Error::InvalidConfig].
+    pub max_buffered_logs: Option<usize>,
+
+    /// `RUST_LOG` value passed to the relay process, defaults to `debug`
+    ///
+    /// Note that [READY_MARKER] is logged at `info` level and won't be seen below it.
+    pub log_level: Option<String>,
+
+    /// Content of the relay `config.toml`, written instead of the generated one
+    ///
+    /// `[network]` `address` & `port` are overridden by the ones nostrd is using.
+    pub config_toml: Option<String>,
+
+    /// Relay name advertised in its NIP-11 document
+    pub relay_name: Option<String>,
+
+    /// Relay description advertised in its NIP-11 document
+    pub relay_description: Option<String>,
+
+    /// Relay operator pubkey advertised in its NIP-11 document, as 64 hex chars
+    pub info_pubkey: Option<String>,
+
+    /// Relay operator contact advertised in its NIP-11 document
+    pub info_contact: Option<String>,
+
+    /// Maximum size of an event accepted by the relay, in bytes
+    pub max_event_bytes: Option<usize>,
+
+    /// Maximum number of messages per second accepted by the relay
+    pub messages_per_sec: Option<u32>,
+
+    /// Event kinds the relay refuses to store
+    pub event_kind_blacklist: Option<Vec<u64>>,
+
+    /// Event kinds the relay only accepts to store
+    ///
+    /// Note that if both lists are set, the relay precedence between them applies.
+    pub event_kind_allowlist: Option<Vec<u64>>,
+
+    /// Only pubkeys allowed to publish events, as 64 hex chars
+    pub pubkey_whitelist: Option<Vec<String>>,
+
+    /// Require NIP-42 client authentication
+    pub nip42_auth: Option<bool>,
+
+    /// Reject events created more than this many seconds in the future
+    pub reject_future_seconds: Option<u64>,
+
+    /// Database backend used by the relay
+    pub database: Database,
+
+    /// Time the `sqlite` feature helpers wait for a database locked by the relay
+    ///
+    /// Defaults to the rusqlite one, 5 seconds. The relay runs its database in WAL mode.
+    pub sqlite_busy_timeout: Option<Duration>,
+
+    /// Interval between the websocket pings sent to clients, in seconds
+    pub ping_interval_seconds: Option<u64>,
+
+    /// Header the client ip is read from, e.g. `X-Forwarded-For` behind a proxy
+    pub remote_ip_header: Option<String>,
+
+    /// Maximum subscriptions a client can open per minute
+    pub subscriptions_per_min: Option<u32>,
+
+    /// Maximum size of a websocket message, in bytes
+    pub max_ws_message_bytes: Option<usize>,
+
+    /// Maximum size of a websocket frame, in bytes
+    pub max_ws_frame_bytes: Option<usize>,
+
+    /// Maximum database connections used by a single client
+    pub db_conns_per_client: Option<u32>,
+
+    /// Size of the channel buffering events waiting to be persisted
+    pub event_persist_buffer: Option<usize>,
+
+    /// Size of the channel broadcasting new events to subscribers
+    pub broadcast_buffer: Option<usize>,
+
+    /// Maximum number of threads the relay runtime uses for blocking tasks
+    pub max_blocking_threads: Option<usize>,
+
+    /// Maximum connections of the relay database reader pool, written to `[database] max_conn`
+    ///
+    /// `min_conn` is lowered to match when below the relay default of 4.
+    pub max_connections: Option<usize>,
+
+    /// Pay-to-relay settings, written to the `[pay_to_relay]` section
+    pub pay_to_relay: Option<PayToRelay>,
+
+    /// NIP-05 verification mode of the relay, `enabled`, `passive` or `disabled`
+    pub verified_users_mode: Option<String>,
+
+    /// NIP-05 domains allowed to publish, any domain if unset
+    pub verified_users_domain_whitelist: Option<Vec<String>>,
+
+    /// NIP-05 domains not allowed to publish
+    pub verified_users_domain_blacklist: Option<Vec<String>>,
+
+    /// Enable the relay `tokio-console` tracing, written to `[diagnostics] tracing`
+    ///
+    /// The console listens on the fixed port 6669, so a single relay at a time can enable it.
+    pub diagnostics_tracing: Option<bool>,
 }
 
-impl<'a> Default for Conf<'a> {
+impl Default for Conf {
     fn default() -> Self {
         Self {
             args: Vec::new(),
+            current_dir: None,
+            envs: Vec::new(),
             attempts: 5,
+            retry_backoff: Duration::from_millis(100),
             ip: None,
             port: None,
+            tls: false,
             binary: None,
+            start_timeout: Duration::from_secs(3),
+            shutdown_timeout: Duration::from_secs(5),
+            #[cfg(unix)]
+            stop_signal: Signal::SIGINT,
+            ready_marker: None,
+            keep_data_dir: false,
+            data_dir_parent: None,
+            log_file: None,
+            max_buffered_logs: None,
+            log_level: None,
+            config_toml: None,
+            relay_name: None,
+            relay_description: None,
+            info_pubkey: None,
+            info_contact: None,
+            max_event_bytes: None,
+            messages_per_sec: None,
+            event_kind_blacklist: None,
+            event_kind_allowlist: None,
+            pubkey_whitelist: None,
+            nip42_auth: None,
+            reject_future_seconds: None,
+            database: Database::Sqlite,
+            sqlite_busy_timeout: None,
+            ping_interval_seconds: None,
+            remote_ip_header: None,
+            subscriptions_per_min: None,
+            max_ws_message_bytes: None,
+            max_ws_frame_bytes: None,
+            db_conns_per_client: None,
+            event_persist_buffer: None,
+            broadcast_buffer: None,
+            max_blocking_threads: None,
+            max_connections: None,
+            pay_to_relay: None,
+            verified_users_mode: None,
+            verified_users_domain_whitelist: None,
+            verified_users_domain_blacklist: None,
+            diagnostics_tracing: None,
         }
     }
 }
 
+impl Conf {
+    /// Returns a [ConfBuilder] starting from the default configuration
+    pub fn builder() -> ConfBuilder {
+        ConfBuilder::default()
+    }
+
+    /// Returns the default configuration using the relay config file at `path`
+    ///
+    /// The network address & port are still set at spawn time.
+    pub fn from_toml_path(path: &Path) -> Result<Conf, Error> {
+        let config = fs::read_to_string(path)?;
+        if let Err(e) = config.parse::<toml::Table>() {
+            return Err(Error::InvalidConfig(e.to_string()));
+        }
+        Ok(Conf {
+            config_toml: Some(config),
+            ..Default::default()
+        })
+    }
+
+    /// Returns the number of spawn attempts, at least one
+    fn attempts(&self) -> u8 {
+        self.attempts.max(1)
+    }
+
+    /// Check the configuration values, so errors surface before spawning the relay
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(ip) = &self.ip {
+            if ip.parse::<IpAddr>().is_err() {
+                return Err(Error::InvalidConfig(format!("invalid ip: {}", ip)));
+            }
+        }
+        if let Some(arg) = self.args.iter().find(|arg| is_reserved_arg(arg)) {
+            return Err(Error::InvalidConfig(format!(
+                "{} is managed by nostrd, set it through Conf instead",
+                arg
+            )));
+        }
+        if let Some(pubkey) = &self.info_pubkey {
+            if !is_hex_pubkey(pubkey) {
+                return Err(Error::InvalidConfig(format!(
+                    "info pubkey must be 64 hex chars: {}",
+                    pubkey
+                )));
+            }
+        }
+        if let Some(pubkeys) = &self.pubkey_whitelist {
+            if let Some(pubkey) = pubkeys.iter().find(|p| !is_hex_pubkey(p)) {
+                return Err(Error::InvalidConfig(format!(
+                    "whitelisted pubkey must be 64 hex chars: {}",
+                    pubkey
+                )));
+            }
+        }
+        if let Some(pay) = &self.pay_to_relay {
+            if pay.enabled && (pay.node_url.is_empty() || pay.api_secret.is_empty()) {
+                return Err(Error::InvalidConfig(
+                    "pay to relay requires a node url and an api secret".into(),
+                ));
+            }
+        }
+        if self.max_buffered_logs == Some(0) {
+            // Every line would be dropped, the log helpers would never return one
+            return Err(Error::InvalidConfig(
+                "max buffered logs must be at least 1".into(),
+            ));
+        }
+        if let Some(config) = &self.config_toml {
+            if let Err(e) = config.parse::<toml::Table>() {
+                return Err(Error::InvalidConfig(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `config.toml` content the relay would be started with, listening on
+    /// `ip`:`port`, without starting it
+    ///
+    /// Fails if [Conf::config_toml] is not valid TOML.
+    pub fn render_config(&self, ip: &str, port: u16) -> Result<String, Error> {
+        let mut table = match &self.config_toml {
+            Some(config) => config
+                .parse()
+                .map_err(|e: toml::de::Error| Error::InvalidConfig(e.to_string()))?,
+            None => toml::Table::new(),
+        };
+        RelayConfig::new(self, ip, port).merge_into(&mut table)?;
+        Ok(table.to_string())
+    }
+}
+
+/// Returns true for the relay flags nostrd passes itself, `--config` & `--db`
+fn is_reserved_arg(arg: &str) -> bool {
+    let flag = arg.split('=').next().unwrap_or(arg);
+    matches!(flag, "--config" | "-c" | "--db" | "-d")
+}
+
+/// Returns true if `pubkey` is a 64 chars hex string
+fn is_hex_pubkey(pubkey: &str) -> bool {
+    pubkey.len() == 64 && pubkey.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Builder for [Conf]
+#[derive(Debug, Default, Clone)]
+pub struct ConfBuilder {
+    conf: Conf,
+}
+
+impl ConfBuilder {
+    /// Append a command line argument
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.conf.args.push(arg.into());
+        self
+    }
+
+    /// Append several command line arguments
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.conf.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set the working directory of the relay process
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.conf.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Append an environment variable passed to the relay process
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.conf.envs.push((key.into(), value.into()));
+        self
+    }
+
+    /// Set the ip to bind to
+    pub fn ip(mut self, ip: impl Into<String>) -> Self {
+        self.conf.ip = Some(ip.into());
+        self
+    }
+
+    /// Set the port to listen on
+    pub fn port(mut self, port: u16) -> Self {
+        self.conf.port = Some(port);
+        self
+    }
+
+    /// Set whether the relay is served over TLS
+    pub fn tls(mut self, tls: bool) -> Self {
+        self.conf.tls = tls;
+        self
+    }
+
+    /// Set the path to the binary
+    pub fn binary(mut self, binary: impl Into<String>) -> Self {
+        self.conf.binary = Some(binary.into());
+        self
+    }
+
+    /// Set the number of spawn attempts
+    pub fn attempts(mut self, attempts: u8) -> Self {
+        self.conf.attempts = attempts;
+        self
+    }
+
+    /// Set the time to wait between two spawn attempts
+    pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+        self.conf.retry_backoff = backoff;
+        self
+    }
+
+    /// Set the time to wait for the relay to be ready
+    pub fn start_timeout(mut self, timeout: Duration) -> Self {
+        self.conf.start_timeout = timeout;
+        self
+    }
+
+    /// Set the time to wait for the relay to exit before killing it
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.conf.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Set the signal sent to stop the relay, e.g. `SIGTERM`
+    #[cfg(unix)]
+    pub fn stop_signal(mut self, signal: Signal) -> Self {
+        self.conf.stop_signal = signal;
+        self
+    }
+
+    /// Set the log line substring signaling the relay is ready
+    pub fn ready_marker(mut self, marker: impl Into<String>) -> Self {
+        self.conf.ready_marker = Some(marker.into());
+        self
+    }
+
+    /// Keep the work directory once [NostrD] is dropped
+    pub fn keep_data_dir(mut self, keep: bool) -> Self {
+        self.conf.keep_data_dir = keep;
+        self
+    }
+
+    /// Set the directory the work directory is created in
+    pub fn data_dir_parent(mut self, parent: impl Into<PathBuf>) -> Self {
+        self.conf.data_dir_parent = Some(parent.into());
+        self
+    }
+
+    /// Set the file every relay log line is appended to
+    pub fn log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.conf.log_file = Some(path.into());
+        self
+    }
+
+    /// Set the maximum number of unread lines kept by each log receiver
+    pub fn max_buffered_logs(mut self, max: usize) -> Self {
+        self.conf.max_buffered_logs = Some(max);
+        self
+    }
+
+    /// Set the `RUST_LOG` value passed to the relay process
+    pub fn log_level(mut self, level: impl Into<String>) -> Self {
+        self.conf.log_level = Some(level.into());
+        self
+    }
+
+    /// Set the content of the relay `config.toml`
+    pub fn config_toml(mut self, config: impl Into<String>) -> Self {
+        self.conf.config_toml = Some(config.into());
+        self
+    }
+
+    /// Set the relay name advertised in its NIP-11 document
+    pub fn relay_name(mut self, name: impl Into<String>) -> Self {
+        self.conf.relay_name = Some(name.into());
+        self
+    }
+
+    /// Set the relay description advertised in its NIP-11 document
+    pub fn relay_description(mut self, description: impl Into<String>) -> Self {
+        self.conf.relay_description = Some(description.into());
+        self
+    }
+
+    /// Set the relay operator pubkey advertised in its NIP-11 document
+    pub fn info_pubkey(mut self, pubkey: impl Into<String>) -> Self {
+        self.conf.info_pubkey = Some(pubkey.into());
+        self
+    }
+
+    /// Set the relay operator contact advertised in its NIP-11 document
+    pub fn info_contact(mut self, contact: impl Into<String>) -> Self {
+        self.conf.info_contact = Some(contact.into());
+        self
+    }
+
+    /// Set the maximum size of an event accepted by the relay
+    pub fn max_event_bytes(mut self, bytes: usize) -> Self {
+        self.conf.max_event_bytes = Some(bytes);
+        self
+    }
+
+    /// Set the maximum number of messages per second accepted by the relay
+    pub fn messages_per_sec(mut self, rate: u32) -> Self {
+        self.conf.messages_per_sec = Some(rate);
+        self
+    }
+
+    /// Set the event kinds the relay refuses to store
+    pub fn event_kind_blacklist(mut self, kinds: impl IntoIterator<Item = u64>) -> Self {
+        self.conf.event_kind_blacklist = Some(kinds.into_iter().collect());
+        self
+    }
+
+    /// Set the only event kinds the relay accepts to store
+    pub fn event_kind_allowlist(mut self, kinds: impl IntoIterator<Item = u64>) -> Self {
+        self.conf.event_kind_allowlist = Some(kinds.into_iter().collect());
+        self
+    }
+
+    /// Set the only pubkeys allowed to publish events
+    pub fn pubkey_whitelist(
+        mut self,
+        pubkeys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.conf.pubkey_whitelist = Some(pubkeys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Require NIP-42 client authentication
+    pub fn nip42_auth(mut self, enabled: bool) -> Self {
+        self.conf.nip42_auth = Some(enabled);
+        self
+    }
+
+    /// Reject events created more than `seconds` in the future
+    pub fn reject_future_seconds(mut self, seconds: u64) -> Self {
+        self.conf.reject_future_seconds = Some(seconds);
+        self
+    }
+
+    /// Set the database backend used by the relay
+    pub fn database(mut self, database: Database) -> Self {
+        self.conf.database = database;
+        self
+    }
+
+    /// Set the time the `sqlite` feature helpers wait for a locked database
+    pub fn sqlite_busy_timeout(mut self, timeout: Duration) -> Self {
+        self.conf.sqlite_busy_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the interval between the websocket pings sent to clients
+    pub fn ping_interval_seconds(mut self, seconds: u64) -> Self {
+        self.conf.ping_interval_seconds = Some(seconds);
+        self
+    }
+
+    /// Set the header the client ip is read from
+    pub fn remote_ip_header(mut self, header: impl Into<String>) -> Self {
+        self.conf.remote_ip_header = Some(header.into());
+        self
+    }
+
+    /// Set the maximum subscriptions a client can open per minute
+    pub fn subscriptions_per_min(mut self, rate: u32) -> Self {
+        self.conf.subscriptions_per_min = Some(rate);
+        self
+    }
+
+    /// Set the maximum size of a websocket message
+    pub fn max_ws_message_bytes(mut self, bytes: usize) -> Self {
+        self.conf.max_ws_message_bytes = Some(bytes);
+        self
+    }
+
+    /// Set the maximum size of a websocket frame
+    pub fn max_ws_frame_bytes(mut self, bytes: usize) -> Self {
+        self.conf.max_ws_frame_bytes = Some(bytes);
+        self
+    }
+
+    /// Set the maximum database connections used by a single client
+    pub fn db_conns_per_client(mut self, conns: u32) -> Self {
+        self.conf.db_conns_per_client = Some(conns);
+        self
+    }
+
+    /// Set the size of the channel buffering events waiting to be persisted
+    pub fn event_persist_buffer(mut self, size: usize) -> Self {
+        self.conf.event_persist_buffer = Some(size);
+        self
+    }
+
+    /// Set the size of the channel broadcasting new events to subscribers
+    pub fn broadcast_buffer(mut self, size: usize) -> Self {
+        self.conf.broadcast_buffer = Some(size);
+        self
+    }
+
+    /// Set the maximum number of threads the relay runtime uses for blocking tasks
+    pub fn max_blocking_threads(mut self, threads: usize) -> Self {
+        self.conf.max_blocking_threads = Some(threads);
+        self
+    }
+
+    /// Set the maximum connections of the relay database reader pool
+    pub fn max_connections(mut self, conns: usize) -> Self {
+        self.conf.max_connections = Some(conns);
+        self
+    }
+
+    /// Set the pay-to-relay settings
+    pub fn pay_to_relay(mut self, pay_to_relay: PayToRelay) -> Self {
+        self.conf.pay_to_relay = Some(pay_to_relay);
+        self
+    }
+
+    /// Set the NIP-05 verification mode of the relay
+    pub fn verified_users_mode(mut self, mode: impl Into<String>) -> Self {
+        self.conf.verified_users_mode = Some(mode.into());
+        self
+    }
+
+    /// Set the NIP-05 domains allowed to publish
+    pub fn verified_users_domain_whitelist(
+        mut self,
+        domains: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.conf.verified_users_domain_whitelist =
+            Some(domains.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the NIP-05 domains not allowed to publish
+    pub fn verified_users_domain_blacklist(
+        mut self,
+        domains: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.conf.verified_users_domain_blacklist =
+            Some(domains.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Enable or disable the relay `tokio-console` tracing
+    pub fn diagnostics_tracing(mut self, tracing: bool) -> Self {
+        self.conf.diagnostics_tracing = Some(tracing);
+        self
+    }
+
+    /// Returns the built [Conf]
+    pub fn build(self) -> Conf {
+        self.conf
+    }
+}
+
 /// Returns a non-used local port if available.
 ///
 /// Note there is a race condition during the time the method check availability and the caller
 pub fn get_available_port() -> Result<u16, Error> {
+    get_available_port_on("127.0.0.1")
+}
+
+/// Returns a non-used port on the given `ip` if available, see [get_available_port]
+pub fn get_available_port_on(ip: &str) -> Result<u16, Error> {
     // using 0 as port let the system assign a port available
-    let t = TcpListener::bind(("127.0.0.1", 0))?; // 0 means the OS choose a free port
+    let t = TcpListener::bind((ip, 0))?; // 0 means the OS choose a free port
     Ok(t.local_addr().map(|s| s.port())?)
 }
 
-/// Struct representing the electrs process with related information
+/// Returns `n` distinct non-used local ports
+///
+/// All the listeners are bound at the same time, so no port is returned twice.
+pub fn get_available_ports(n: usize) -> Result<Vec<u16>, Error> {
+    let listeners = (0..n)
+        .map(|_| TcpListener::bind(("127.0.0.1", 0)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(listeners
+        .iter()
+        .map(|t| t.local_addr().map(|s| s.port()))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Returns the path of the relay binary fetched at build time, if any
+#[cfg(feature = "download")]
+pub fn downloaded_exe_path() -> Option<String> {
+    option_env!("NOSTRD_DOWNLOADED_EXE").map(Into::into)
+}
+
+#[cfg(not(feature = "download"))]
+fn downloaded_exe_path() -> Option<String> {
+    None
+}
+
+/// Struct representing the relay process with related information
+///
+/// The relay is a guard: dropping it stops the process and removes the work directory (unless
+/// [Conf::keep_data_dir] is set). It is [Send], so it can be moved into threads or tasks.
 pub struct NostrD {
     /// Process child handle, used to terminate the process when this struct is dropped
     pub process: Child,
-    /// Work directory, removed when dropped
-    pub work_dir: TempDir,
-    /// A buffer receiving stdout and stderr
-    pub logs: Receiver<String>,
+    /// Work directory, removed when dropped unless [Conf::keep_data_dir] is set
+    work_dir: TempDir,
+    /// A buffer receiving stdout and stderr, [LogReceiver::try_recv_tagged] tells them apart
+    pub logs: LogReceiver,
     /// The port we listen to
     pub port: u16,
     /// the address we listen to
     pub addr: String,
     /// Path to the binary
     pub binary: PathBuf,
+    /// Arguments the process is spawned with
+    args: Vec<String>,
+    /// Working directory of the process
+    current_dir: PathBuf,
+    /// Configuration the process is spawned with
+    conf: Conf,
+    /// Time the last (re)start took to be ready
+    startup_duration: Duration,
+    /// Spawn attempts the last (re)start took
+    attempts_used: u8,
+    /// The port was picked, not taken from [Conf::port]
+    port_changed: bool,
+    /// Version printed by the binary, once asked for
+    relay_version: OnceLock<String>,
+    /// Broadcast of the log lines, see [NostrD::log_stream]
+    #[cfg(feature = "tokio")]
+    broadcast: tokio::sync::broadcast::Sender<String>,
 }
 
+/// Returns true if the relay of a resolved [Conf] accepts TCP connections
+fn is_listening(conf: &Conf) -> bool {
+    let ip = conf.ip.as_deref().expect("resolved");
+    TcpStream::connect((ip, conf.port.expect("resolved"))).is_ok()
+}
+
+/// Read a line, newline included, returns `None` on EOF
+///
+/// Invalid UTF-8 is replaced rather than dropping the line.
 fn try_read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
     let mut buffer = Vec::new();
-    // Try to read until a newline
     match reader.read_until(b'\n', &mut buffer)? {
-        0 => Ok(None), // EOF reached or no data available
-        _ => {
-            if let Ok(line) = String::from_utf8(buffer) {
-                Ok(Some(line))
-            } else {
-                Ok(None)
-            }
+        0 => Ok(None),
+        _ => Ok(Some(String::from_utf8_lossy(&buffer).into_owned())),
+    }
+}
+
+/// Relay log substrings meaning it won't get ready, see [Error::StartupFailed]
+const FATAL_PATTERNS: &[&str] = &[
+    "Address already in use",
+    "Database directory does not exist",
+    "unable to open database file",
+];
+
+/// Looks in the relay output for the ready marker or a fatal error
+struct StartupScan {
+    marker: String,
+    /// `thread '..' panicked at ..` line, the panic message is on the next one
+    panic: Option<String>,
+}
+
+impl StartupScan {
+    fn new(marker: &str) -> StartupScan {
+        StartupScan {
+            marker: marker.into(),
+            panic: None,
+        }
+    }
+
+    /// Returns `Ok` once the marker is seen, the offending line on a fatal error
+    fn scan(&mut self, line: &str) -> Option<Result<(), String>> {
+        if let Some(panic) = self.panic.take() {
+            return Some(Err(format!("{} {}", panic, line.trim_end())));
+        }
+        if line.contains(&self.marker) {
+            return Some(Ok(()));
+        }
+        let line = logs::strip_ansi(line.trim_end());
+        if line.contains("panicked at") {
+            self.panic = Some(line);
+            None
+        } else if FATAL_PATTERNS.iter().any(|pattern| line.contains(pattern)) {
+            Some(Err(line))
+        } else {
+            None
         }
     }
 }
 
-impl NostrD {
-    /// Create a new nostr process
-    pub fn new() -> Result<NostrD, Error> {
-        NostrD::with_conf(&Conf::default())
+/// Destinations of the lines read from one of the relay output streams
+#[derive(Clone)]
+struct LogSink {
+    logs: LogSender,
+    source: LogSource,
+    file: Option<Arc<Mutex<File>>>,
+    #[cfg(feature = "tokio")]
+    broadcast: tokio::sync::broadcast::Sender<String>,
+}
+
+impl LogSink {
+    /// Returns the stdout & stderr log sinks and the receiver of both
+    ///
+    /// Lines are appended to [Conf::log_file] too, if any.
+    fn new(
+        conf: &Conf,
+        #[cfg(feature = "tokio")] broadcast: &tokio::sync::broadcast::Sender<String>,
+    ) -> Result<(LogSink, LogSink, LogReceiver), Error> {
+        let file = match &conf.log_file {
+            Some(path) => Some(Arc::new(Mutex::new(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?,
+            ))),
+            None => None,
+        };
+        let (logs, receiver) = log_channel(conf.max_buffered_logs);
+        let sink = |source| LogSink {
+            logs: logs.clone(),
+            source,
+            file: file.clone(),
+            #[cfg(feature = "tokio")]
+            broadcast: broadcast.clone(),
+        };
+        Ok((sink(LogSource::Stdout), sink(LogSource::Stderr), receiver))
     }
 
-    /// Create a new process using given [Conf]
-    pub fn with_conf(conf: &Conf) -> Result<NostrD, Error> {
+    /// Send a line to all destinations, returns false once the logs receiver is dropped
+    fn send(&self, line: String) -> bool {
+        if let Some(file) = &self.file {
+            // Written line by line, unbuffered, so the tail survives a crash
+            let mut file = file.lock().expect("poisoned");
+            let newline = if line.ends_with('\n') { "" } else { "\n" };
+            let _ = write!(file, "{}{}", line, newline);
+        }
+        #[cfg(feature = "tokio")]
+        let _ = self.broadcast.send(line.clone());
+        self.logs.send(self.source, line)
+    }
+}
+
+/// A relay process ready to accept connections
+struct Spawned {
+    process: Child,
+    logs: LogReceiver,
+    /// Time elapsed from spawn to readiness of the successful attempt
+    startup_duration: Duration,
+    /// Number of attempts it took, the successful one included
+    attempts: u8,
+    /// Port the relay listens on
+    port: u16,
+}
+
+/// Everything needed to spawn the relay process
+struct Setup {
+    binary: PathBuf,
+    work_dir: TempDir,
+    args: Vec<String>,
+    /// Working directory of the relay process
+    current_dir: PathBuf,
+    /// Configuration with ip and port resolved
+    conf: Conf,
+    /// No fixed [Conf::port], retries pick another port
+    auto_port: bool,
+    #[cfg(feature = "tokio")]
+    broadcast: tokio::sync::broadcast::Sender<String>,
+}
+
+impl Setup {
+    /// Resolve the binary, create the work directory and write the relay config
+    fn new(conf: &Conf) -> Result<Setup, Error> {
+        conf.validate()?;
         let mut args = conf.args.clone();
         let ip = conf.ip.clone().unwrap_or("127.0.0.1".into());
-        let port = conf.port.unwrap_or(get_available_port()?);
-
-        let file_path = file!();
-        let mut bin_dir = Path::new(file_path)
-            .parent()
-            .unwrap()
-            .parent()
-            .unwrap()
-            .to_path_buf();
-        bin_dir.push("bin");
-        bin_dir.push("nostr-rs-relay_0_9_0_linux");
-
-        let bin = if let Some(bin) = conf.binary.clone() {
+        let port = match conf.port {
+            // Like the OS would for 0, pick an available port
+            None | Some(0) => get_available_port_on(&ip)?,
+            // Retrying a taken fixed port is pointless, fail before spawning
+            Some(port) => match TcpListener::bind((ip.as_str(), port)) {
+                Ok(_) => port,
+                Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                    return Err(Error::PortInUse(port))
+                }
+                Err(e) => return Err(e.into()),
+            },
+        };
+
+        let binary: PathBuf = if let Some(bin) = &conf.binary {
+            bin.into()
+        } else if let Some(bin) = env::var_os(EXE_ENV_VAR) {
+            bin.into()
+        } else if let Some(bin) = downloaded_exe_path()
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(versions::vendored_exe_path())
+            .find(|bin| bin.is_file())
+        {
             bin
-        } else if let Some(bin) = &bin_dir.to_str() {
-            bin.to_string()
         } else {
-            panic!("no valid binary path")
+            return Err(Error::NoBinary);
         };
 
-        let exe = Path::new(&bin);
-        if !exe.exists() {
-            panic!("path {:?} does not exists!", exe);
+        if !binary.exists() {
+            return Err(Error::BinaryNotFound(binary));
+        }
+        if !binary.is_file() {
+            return Err(Error::NotAFile(binary));
         }
-        if !exe.is_file() {
-            panic!(" path {:?} is not a file!", exe);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if binary.metadata()?.permissions().mode() & 0o111 == 0 {
+                return Err(Error::BinaryNotExecutable(binary));
+            }
         }
+        // The relay runs in another directory, a relative path would not resolve there
+        let binary = fs::canonicalize(binary)?;
 
         // create the temp dir
-        let work_dir = TempDir::with_prefix("nostrd_").unwrap();
+        let mut builder = tempfile::Builder::new();
+        builder
+            .prefix("nostrd_")
+            .disable_cleanup(conf.keep_data_dir);
+        let work_dir = match &conf.data_dir_parent {
+            Some(parent) => builder.tempdir_in(fs::canonicalize(parent)?)?,
+            None => builder.tempdir()?,
+        };
 
         // config file
-        let mut file = File::create(work_dir.child("config.toml"))?;
-        writeln!(&file, "[network]").unwrap();
-        writeln!(file, "address = \"{}\"", ip.clone()).unwrap();
-        writeln!(file, "port = \"{}\"", port).unwrap();
+        let mut file = File::create(work_dir.path().join(CONFIG_FILE))?;
+        file.write_all(conf.render_config(&ip, port)?.as_bytes())?;
         drop(file);
 
         // config
-        args.push("--config");
-        let cfg_path = work_dir.child("config.toml");
+        args.push("--config".into());
+        let cfg_path = work_dir.path().join(CONFIG_FILE);
         let path = cfg_path.as_path().to_str().expect("hardcoded");
-        args.push(path);
+        args.push(path.into());
 
         // db location
-        args.push("--db");
-        args.push(work_dir.path().to_str().expect("hardcoded"));
-
-        let (sender, logs) = mpsc::channel();
-
-        std::env::set_var("RUST_LOG", "debug");
-        let mut p = None;
-        #[allow(clippy::never_loop)]
-        'f: for _ in 0..conf.attempts {
-            let mut process = Command::new(exe)
-                .args(args.clone())
-                .stderr(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()?;
-            let timeout = Instant::now() + Duration::from_secs(3);
-            let stdout = process.stdout.take().unwrap();
-            let mut stdout_reader = BufReader::new(stdout);
-            let s = sender.clone();
-            let stop = Arc::new(AtomicBool::new(false));
-            let stop2 = stop.clone();
-            thread::spawn(move || loop {
-                if let Ok(Some(line)) = try_read_line(&mut stdout_reader) {
-                    let _ = s.send(line);
-                } else if stop2.load(Relaxed) {
-                    break;
-                }
-            });
-
-            loop {
-                if Instant::now() > timeout {
-                    let _ = process.kill();
-                    stop.store(true, Relaxed);
-                } else if let Ok(log) = logs.try_recv() {
-                    if log.contains("control message listener started") {
-                        p = Some(process);
+        if conf.database == Database::Sqlite {
+            args.push("--db".into());
+            args.push(work_dir.path().to_str().expect("hardcoded").into());
+        }
+
+        // Kept out of the resolved conf, the work directory is gone with the relay
+        let current_dir = match &conf.current_dir {
+            Some(dir) => fs::canonicalize(dir)?,
+            None => work_dir.path().to_path_buf(),
+        };
+        let auto_port = matches!(conf.port, None | Some(0));
+        let conf = Conf {
+            ip: Some(ip),
+            port: Some(port),
+            ..conf.clone()
+        };
+        Ok(Setup {
+            binary,
+            work_dir,
+            args,
+            current_dir,
+            conf,
+            auto_port,
+            #[cfg(feature = "tokio")]
+            broadcast: tokio::sync::broadcast::channel(LOG_STREAM_CAPACITY).0,
+        })
+    }
+}
+
+/// Forward lines of `reader` to `sink` from a new thread, until EOF or the logs receiver is
+/// dropped, notifying `ready` when the marker is seen or a fatal error is logged
+///
+/// `trim_newline` sends the lines without their line ending, as stderr lines always were.
+fn forward_logs<R: BufRead + Send + 'static>(
+    mut reader: R,
+    sink: LogSink,
+    trim_newline: bool,
+    marker: &str,
+    ready: Sender<Result<(), String>>,
+) {
+    // Dropped once signaled, the later lines are not scanned
+    let mut startup = Some((StartupScan::new(marker), ready));
+    thread::spawn(move || {
+        while let Ok(Some(mut line)) = try_read_line(&mut reader) {
+            if trim_newline {
+                line.truncate(line.trim_end_matches(&['\r', '\n'][..]).len());
+            }
+            // Signal readiness once the marker is in the logs
+            let signal = startup.as_mut().and_then(|(scan, _)| scan.scan(&line));
+            let sent = sink.send(line);
+            if let Some(signal) = signal {
+                let (_, ready) = startup.take().expect("scanned");
+                let _ = ready.send(signal);
+            }
+            if !sent {
+                break;
+            }
+        }
+    });
+}
+
+/// Spawn the relay process, retrying until it logs it's ready
+///
+/// If `repick_config` is set, each retry picks another available port and rewrites this config
+/// file with it.
+fn spawn(
+    exe: &Path,
+    args: &[String],
+    current_dir: &Path,
+    conf: &Conf,
+    repick_config: Option<&Path>,
+    (stdout_sink, stderr_sink, receiver): (LogSink, LogSink, LogReceiver),
+) -> Result<Spawned, Error> {
+    let mut conf = conf.clone();
+    let ready_marker = conf.ready_marker.as_deref().unwrap_or(READY_MARKER);
+    let log_level = conf.log_level.as_deref().unwrap_or("debug");
+
+    let mut p = None;
+    'f: for attempt in 0..conf.attempts() {
+        if attempt > 0 {
+            sleep(conf.retry_backoff);
+            if let Some(path) = repick_config {
+                let ip = conf.ip.as_deref().expect("resolved");
+                let port = get_available_port_on(ip)?;
+                fs::write(path, conf.render_config(ip, port)?)?;
+                conf.port = Some(port);
+            }
+        }
+        let start = Instant::now();
+        let mut process = Command::new(exe)
+            .args(args)
+            .current_dir(current_dir)
+            .env("RUST_LOG", log_level)
+            .envs(conf.envs.iter().map(|(k, v)| (k, v)))
+            .stderr(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        // The reader threads look for the marker, so the startup lines still reach the logs
+        let (ready_sender, ready) = mpsc::channel();
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        let stderr = BufReader::new(process.stderr.take().unwrap());
+        forward_logs(
+            stdout,
+            stdout_sink.clone(),
+            false,
+            ready_marker,
+            ready_sender.clone(),
+        );
+        forward_logs(
+            stderr,
+            stderr_sink.clone(),
+            true,
+            ready_marker,
+            ready_sender,
+        );
+
+        // The marker can be logged before the socket accepts connections, and before the relay
+        // fails to bind it: a process squatting the port accepts connections too, the relay is
+        // up once still alive a poll after the port is listening
+        let deadline = Instant::now() + conf.start_timeout;
+        let fatal = match ready.recv_timeout(conf.start_timeout) {
+            Ok(Ok(())) => {
+                let mut listening = false;
+                let mut fatal = None;
+                while Instant::now() < deadline {
+                    if let Ok(Err(line)) = ready.try_recv() {
+                        fatal = Some(line);
+                        break;
+                    }
+                    if !matches!(process.try_wait(), Ok(None)) {
+                        // Exited, its fatal line may still be in the pipes
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        fatal = ready.recv_timeout(remaining).ok().and_then(Result::err);
+                        break;
+                    }
+                    if listening {
+                        p = Some((process, start.elapsed(), attempt + 1));
                         break 'f;
-                    } else {
-                        sleep(Duration::from_millis(10));
                     }
+                    listening = is_listening(&conf);
+                    sleep(Duration::from_millis(10));
                 }
+                fatal
             }
-        }
-        let mut process = if let Some(p) = p {
-            p
-        } else {
-            panic!("Fail to start NostrD after {} attempts", conf.attempts);
+            Ok(Err(line)) => Some(line),
+            Err(_) => None,
         };
-        let stderr = process.stderr.take().unwrap();
-
-        thread::spawn(move || {
-            let reader = BufReader::new(stderr);
-            for line in reader.lines() {
-                sender.send(line.unwrap()).unwrap();
+        let _ = process.kill();
+        let _ = process.wait();
+        if let Some(line) = fatal {
+            // Another process took the picked port, the next attempt picks another one
+            let retry = repick_config.is_some() && line.contains("Address already in use");
+            if !retry {
+                // Retrying won't help, stop there
+                return Err(Error::StartupFailed(line));
             }
+        }
+    }
+    let (process, startup_duration, attempts) = if let Some(p) = p {
+        p
+    } else {
+        return Err(Error::SpawnFailed {
+            attempts: conf.attempts(),
         });
+    };
 
-        Ok(NostrD {
-            process,
-            work_dir,
-            logs,
-            addr: ip.clone(),
-            port,
-            binary: exe.to_path_buf(),
-        })
+    Ok(Spawned {
+        process,
+        logs: receiver,
+        startup_duration,
+        attempts,
+        port: conf.port.expect("resolved"),
+    })
+}
+
+impl NostrD {
+    /// Create a new nostr process
+    pub fn new() -> Result<NostrD, Error> {
+        NostrD::with_conf(&Conf::default())
+    }
+
+    /// Spawn a relay with the default [Conf], same as [NostrD::new]
+    pub fn spawn() -> Result<NostrD, Error> {
+        NostrD::new()
+    }
+
+    /// Create a new process using given [Conf]
+    pub fn with_conf(conf: &Conf) -> Result<NostrD, Error> {
+        let setup = Setup::new(conf)?;
+        let sinks = LogSink::new(
+            &setup.conf,
+            #[cfg(feature = "tokio")]
+            &setup.broadcast,
+        )?;
+        let config_path = setup.work_dir.path().join(CONFIG_FILE);
+        let spawned = spawn(
+            &setup.binary,
+            &setup.args,
+            &setup.current_dir,
+            &setup.conf,
+            setup.auto_port.then_some(config_path.as_path()),
+            sinks,
+        )?;
+        Ok(NostrD::from_setup(setup, spawned))
+    }
+
+    fn from_setup(setup: Setup, spawned: Spawned) -> NostrD {
+        let port_changed = setup.conf.port != Some(spawned.port);
+        NostrD {
+            process: spawned.process,
+            work_dir: setup.work_dir,
+            logs: spawned.logs,
+            addr: setup.conf.ip.clone().expect("resolved"),
+            port: spawned.port,
+            binary: setup.binary,
+            args: setup.args,
+            current_dir: setup.current_dir,
+            conf: Conf {
+                port: Some(spawned.port),
+                ..setup.conf
+            },
+            startup_duration: spawned.startup_duration,
+            attempts_used: spawned.attempts,
+            port_changed,
+            relay_version: OnceLock::new(),
+            #[cfg(feature = "tokio")]
+            broadcast: setup.broadcast,
+        }
+    }
+
+    /// Restart the process, keeping the same work directory, config and port
+    pub fn restart(&mut self) -> Result<(), Error> {
+        self.kill()?;
+        let sinks = LogSink::new(
+            &self.conf,
+            #[cfg(feature = "tokio")]
+            &self.broadcast,
+        )?;
+        let spawned = spawn(
+            &self.binary,
+            &self.args,
+            &self.current_dir,
+            &self.conf,
+            None,
+            sinks,
+        )?;
+        self.process = spawned.process;
+        self.logs = spawned.logs;
+        self.startup_duration = spawned.startup_duration;
+        self.attempts_used = spawned.attempts;
+        Ok(())
     }
 
     /// Return the current workdir path of the running electrs
@@ -218,36 +1280,219 @@ impl NostrD {
         self.work_dir.path().to_path_buf()
     }
 
-    /// terminate the process
-    pub fn kill(&mut self) -> Result<(), Error> {
+    /// Return the path of the relay SQLite database
+    pub fn db_path(&self) -> PathBuf {
+        self.work_dir.path().join("nostr.db")
+    }
+
+    /// Returns the [Conf] the relay is spawned with, [Conf::ip] & [Conf::port] filled in
+    pub fn effective_conf(&self) -> Conf {
+        self.conf.clone()
+    }
+
+    /// Returns true if the database outlives this struct: the work directory is kept
+    /// ([Conf::keep_data_dir]) or the database is not stored in it ([Database::Postgres])
+    pub fn is_persistent(&self) -> bool {
+        self.conf.keep_data_dir || self.conf.database != Database::Sqlite
+    }
+
+    /// Return the time the relay took to be ready, for the last successful (re)start
+    pub fn startup_duration(&self) -> Duration {
+        self.startup_duration
+    }
+
+    /// Return the number of spawn attempts the last (re)start took
+    ///
+    /// See [Conf::attempts](field@Conf::attempts).
+    pub fn attempts_used(&self) -> u8 {
+        self.attempts_used
+    }
+
+    /// Returns true if a retry moved the relay off the port picked first
+    ///
+    /// See [Conf::attempts](field@Conf::attempts). Always false for a fixed [Conf::port],
+    /// restarts keep the port.
+    pub fn port_changed(&self) -> bool {
+        self.port_changed
+    }
+
+    /// Return the version of the running relay binary, e.g. `0.9.0`
+    ///
+    /// The binary is run with `--version` on the first call, the result is cached.
+    pub fn relay_version(&self) -> Result<String, Error> {
+        if let Some(version) = self.relay_version.get() {
+            return Ok(version.clone());
+        }
+        let output = Command::new(&self.binary).arg("--version").output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // Printed as `nostr-rs-relay 0.9.0`
+        let version = stdout
+            .split_whitespace()
+            .last()
+            .filter(|_| output.status.success())
+            .ok_or_else(|| Error::UnknownVersion(stdout.trim().to_string()))?;
+        Ok(self.relay_version.get_or_init(|| version.into()).clone())
+    }
+
+    /// Return the id of the relay process
+    pub fn pid(&self) -> u32 {
+        self.process.id()
+    }
+
+    /// Return the path of the generated relay config file
+    pub fn config_path(&self) -> PathBuf {
+        self.work_dir.path().join(CONFIG_FILE)
+    }
+
+    /// Returns true if the process has not exited
+    pub fn is_alive(&mut self) -> bool {
+        matches!(self.process.try_wait(), Ok(None))
+    }
+
+    /// terminate the process, escalate to a hard kill after [Conf::shutdown_timeout]
+    ///
+    /// Returns the exit status of the process, also if it already exited.
+    ///
+    /// Called right after startup, the relay may be killed by `Conf::stop_signal` before its
+    /// handler is installed: it then exits on the signal, without logging its shutdown.
+    pub fn kill(&mut self) -> Result<ExitStatus, Error> {
+        if let Some(status) = self.process.try_wait()? {
+            return Ok(status);
+        }
         self.inner_kill()?;
         // Wait for the process to exit
-        match self.process.wait() {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.into()),
+        let timeout = Instant::now() + self.conf.shutdown_timeout;
+        loop {
+            if let Some(status) = self.process.try_wait()? {
+                return Ok(status);
+            }
+            if Instant::now() > timeout {
+                self.process.kill()?;
+                return Ok(self.process.wait()?);
+            }
+            thread::sleep(Duration::from_millis(10));
         }
     }
 
     /// clear the log buffer
     pub fn clear_logs(&mut self) {
-        while self.logs.try_recv().is_ok() {}
+        self.logs.clear();
+    }
+
+    /// Returns all the log lines currently buffered, without blocking
+    pub fn drain_logs(&mut self) -> Vec<String> {
+        self.logs_iter().collect()
+    }
+
+    /// Iterate over the log lines currently buffered, without blocking
+    pub fn logs_iter(&mut self) -> impl Iterator<Item = String> + '_ {
+        self.logs.try_iter()
     }
 
+    /// Wait for the next log line, returns `None` if none is received before `timeout`
+    pub fn next_log(&self, timeout: Duration) -> Result<Option<String>, Error> {
+        match self.logs.recv_timeout(timeout) {
+            Ok(line) => Ok(Some(line)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::LogsDisconnected),
+        }
+    }
+
+    /// Wait for a log line containing `needle`, consuming the lines received until then
+    pub fn wait_for_log(&mut self, needle: &str, timeout: Duration) -> Result<String, Error> {
+        self.wait_for_line(timeout, |line| line.contains(needle).then_some(line))
+    }
+
+    /// Wait for a client to connect to the relay, see [CONNECTION_MARKER]
+    ///
+    /// The log lines received until then are consumed.
+    pub fn wait_for_connection(&self, timeout: Duration) -> Result<(), Error> {
+        self.wait_for_line(timeout, |line| {
+            line.contains(CONNECTION_MARKER).then_some(())
+        })
+    }
+
+    /// Wait for a log line `f` maps to `Some`, consuming the lines received until then
+    fn wait_for_line<T>(
+        &self,
+        timeout: Duration,
+        mut f: impl FnMut(String) -> Option<T>,
+    ) -> Result<T, Error> {
+        let deadline = Instant::now() + timeout;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            match self.logs.recv_timeout(remaining) {
+                Ok(line) => {
+                    if let Some(found) = f(line) {
+                        return Ok(found);
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Wait until the relay accepts TCP connections on its port
+    pub fn wait_ws_ready(&self, timeout: Duration) -> Result<(), Error> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if TcpStream::connect((self.addr.as_str(), self.port)).is_ok() {
+                return Ok(());
+            }
+            sleep(Duration::from_millis(10));
+        }
+        Err(Error::Timeout)
+    }
+
+    #[cfg(unix)]
     fn inner_kill(&mut self) -> Result<(), Error> {
-        // Send SIGINT signal to electrsd
         Ok(nix::sys::signal::kill(
             nix::unistd::Pid::from_raw(self.process.id() as i32),
-            nix::sys::signal::SIGINT,
+            self.conf.stop_signal,
         )?)
     }
 
+    #[cfg(windows)]
+    fn inner_kill(&mut self) -> Result<(), Error> {
+        // There is no SIGINT on windows, terminate the process
+        Ok(self.process.kill()?)
+    }
+
+    /// Returns the address the relay listens on
+    pub fn socket_addr(&self) -> SocketAddr {
+        let ip: IpAddr = self.addr.parse().expect("validated");
+        SocketAddr::new(ip, self.port)
+    }
+
+    /// Returns the websocket endpoint, `wss://` if [Conf::tls] is set, `ws://` otherwise
     pub fn url(&self) -> String {
+        if self.conf.tls {
+            self.wss_url()
+        } else {
+            self.ws_url()
+        }
+    }
+
+    /// Returns the plain websocket endpoint
+    pub fn ws_url(&self) -> String {
         format!("ws://{}:{}", self.addr, self.port)
     }
+
+    /// Returns the TLS websocket endpoint
+    pub fn wss_url(&self) -> String {
+        format!("wss://{}:{}", self.addr, self.port)
+    }
+
+    /// Returns the plain HTTP endpoint, serving the relay information document
+    pub fn http_url(&self) -> String {
+        format!("http://{}:{}", self.addr, self.port)
+    }
 }
 
 impl Drop for NostrD {
     fn drop(&mut self) {
-        let _ = self.kill();
+        if let Err(e) = self.kill() {
+            log::warn!("failed to stop relay process {}: {}", self.pid(), e);
+        }
     }
 }
@@ -0,0 +1,252 @@
+use crate::{Error, NostrD};
+use log::Level;
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{RecvError, RecvTimeoutError, TryRecvError},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+/// A relay log line, as formatted by its `tracing` subscriber
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// RFC 3339 timestamp of the line
+    pub timestamp: String,
+    /// Severity of the line
+    pub level: Level,
+    /// Module emitting the line, e.g. `nostr_rs_relay::server`
+    pub target: String,
+    /// Logged message, without the trailing newline
+    pub message: String,
+}
+
+impl NostrD {
+    /// Parse a relay log line, returns `None` if it's not in the `tracing` format
+    pub fn parse_log_line(line: &str) -> Option<LogRecord> {
+        let line = strip_ansi(line);
+        let (timestamp, rest) = line.trim_start().split_once(' ')?;
+        let (level, mut rest) = rest.trim_start().split_once(' ')?;
+        let level = level.parse().ok()?;
+        // Skip the spans (`name{fields}:`) preceding the target
+        let (target, message) = loop {
+            let (head, tail) = rest.split_once(": ")?;
+            if !head.ends_with('}') {
+                break (head, tail);
+            }
+            rest = tail;
+        };
+        Some(LogRecord {
+            timestamp: timestamp.into(),
+            level,
+            target: target.into(),
+            message: message.trim_end().into(),
+        })
+    }
+
+    /// Wait for a log line of `level` or more severe, consuming the lines received until then
+    pub fn wait_for_level(&mut self, level: Level, timeout: Duration) -> Result<LogRecord, Error> {
+        self.wait_for_line(timeout, |line| {
+            NostrD::parse_log_line(&line).filter(|record| record.level <= level)
+        })
+    }
+}
+
+/// Remove the ANSI escape sequences coloring the output
+pub(crate) fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // CSI sequences end with a letter, e.g. `\u{1b}[2m`
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Output stream of the relay process a log line is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSource {
+    /// Standard output, where the relay logs
+    Stdout,
+    /// Standard error, where panics are printed
+    Stderr,
+}
+
+/// Receiving half of a relay log channel, with the same methods as [std::sync::mpsc::Receiver]
+///
+/// Lines of both streams are received in order, the `_tagged` variants tell which stream each
+/// line is from. Keeps at most [Conf::max_buffered_logs](crate::Conf::max_buffered_logs) unread lines, the
+/// oldest ones are dropped first.
+pub struct LogReceiver {
+    shared: Arc<Shared>,
+}
+
+/// Sending half of a relay log channel
+pub(crate) struct LogSender {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    pushed: Condvar,
+    cap: Option<usize>,
+}
+
+struct State {
+    lines: VecDeque<(LogSource, String)>,
+    senders: usize,
+    receiver: bool,
+}
+
+/// Returns a log channel keeping at most `cap` unread lines, unbounded if not set
+pub(crate) fn log_channel(cap: Option<usize>) -> (LogSender, LogReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            lines: VecDeque::new(),
+            senders: 1,
+            receiver: true,
+        }),
+        pushed: Condvar::new(),
+        cap,
+    });
+    (
+        LogSender {
+            shared: shared.clone(),
+        },
+        LogReceiver { shared },
+    )
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("poisoned")
+    }
+}
+
+impl LogSender {
+    /// Send a line, dropping the oldest unread ones beyond the cap
+    ///
+    /// Returns false once the receiver is dropped.
+    pub(crate) fn send(&self, source: LogSource, line: String) -> bool {
+        let mut state = self.shared.state();
+        if !state.receiver {
+            return false;
+        }
+        state.lines.push_back((source, line));
+        if let Some(cap) = self.shared.cap {
+            while state.lines.len() > cap {
+                state.lines.pop_front();
+            }
+        }
+        self.shared.pushed.notify_all();
+        true
+    }
+}
+
+impl Clone for LogSender {
+    fn clone(&self) -> Self {
+        self.shared.state().senders += 1;
+        LogSender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for LogSender {
+    fn drop(&mut self) {
+        self.shared.state().senders -= 1;
+        self.shared.pushed.notify_all();
+    }
+}
+
+impl LogReceiver {
+    /// Return a line if one is buffered, without blocking
+    pub fn try_recv(&self) -> Result<String, TryRecvError> {
+        self.try_recv_tagged().map(|(_, line)| line)
+    }
+
+    /// Return a line and its stream if one is buffered, without blocking
+    pub fn try_recv_tagged(&self) -> Result<(LogSource, String), TryRecvError> {
+        let mut state = self.shared.state();
+        match state.lines.pop_front() {
+            Some(line) => Ok(line),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Wait for a line, fails once the relay output is closed and all lines are received
+    pub fn recv(&self) -> Result<String, RecvError> {
+        let mut state = self.shared.state();
+        loop {
+            match state.lines.pop_front() {
+                Some((_, line)) => return Ok(line),
+                None if state.senders == 0 => return Err(RecvError),
+                None => state = self.shared.pushed.wait(state).expect("poisoned"),
+            }
+        }
+    }
+
+    /// Wait for a line at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<String, RecvTimeoutError> {
+        self.recv_timeout_tagged(timeout).map(|(_, line)| line)
+    }
+
+    /// Wait for a line and its stream at most `timeout`
+    pub fn recv_timeout_tagged(
+        &self,
+        timeout: Duration,
+    ) -> Result<(LogSource, String), RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state();
+        loop {
+            if let Some(line) = state.lines.pop_front() {
+                return Ok(line);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .ok_or(RecvTimeoutError::Timeout)?;
+            state = self
+                .shared
+                .pushed
+                .wait_timeout(state, remaining)
+                .expect("poisoned")
+                .0;
+        }
+    }
+
+    /// Drop the lines currently buffered
+    pub(crate) fn clear(&self) {
+        self.shared.state().lines.clear();
+    }
+
+    /// Iterate over the lines currently buffered, without blocking
+    pub fn try_iter(&self) -> impl Iterator<Item = String> + '_ {
+        std::iter::from_fn(move || self.try_recv().ok())
+    }
+
+    /// Iterate over the lines until the relay output is closed
+    pub fn iter(&self) -> impl Iterator<Item = String> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+}
+
+impl Drop for LogReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.receiver = false;
+        state.lines.clear();
+    }
+}
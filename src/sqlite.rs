@@ -0,0 +1,140 @@
+use crate::{Error, NostrD};
+#[cfg(feature = "client")]
+use nostr::event::Event;
+#[cfg(feature = "client")]
+use rusqlite::OptionalExtension;
+use rusqlite::{Connection, OpenFlags};
+#[cfg(feature = "client")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl NostrD {
+    /// Returns the number of events stored in the relay SQLite database
+    pub fn event_count(&self) -> Result<u64, Error> {
+        let conn = self.open_db(OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM event", [], |row| row.get(0))?;
+        Ok(count as u64)
+    }
+
+    /// Delete all the events stored in the relay SQLite database
+    ///
+    /// The relay keeps running, it should not be receiving events meanwhile. Requires the
+    /// `sqlite` feature.
+    pub fn clear_events(&mut self) -> Result<(), Error> {
+        let conn = self.open_db(OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+        conn.execute_batch("BEGIN; DELETE FROM tag; DELETE FROM event; COMMIT;")?;
+        Ok(())
+    }
+
+    /// Insert signed `events` directly in the relay SQLite database, skipping the known ones
+    ///
+    /// Rows are written like the relay does: only single letter tags are indexed, a replaceable
+    /// event deletes the older versions and is skipped if a newer one is stored, a deletion
+    /// (kind 5) hides the author's events it targets. Requires both the `sqlite` & `client`
+    /// features.
+    #[cfg(feature = "client")]
+    pub fn seed_events(&mut self, events: &[Event]) -> Result<(), Error> {
+        let mut conn = self.open_db(OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+        let first_seen = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default() as i64;
+        let tx = conn.transaction()?;
+        for event in events {
+            let created_at = event.created_at.as_secs() as i64;
+            let kind = i64::from(u16::from(event.kind));
+            let author = event.pubkey.as_bytes().as_slice();
+            // Replaceable & parameterized replaceable kinds, as the relay defines them
+            let replaceable = matches!(kind, 0 | 3 | 41 | 10000..=19999);
+            let d_tag = match kind {
+                30000..=39999 => Some(event.tags.identifier().unwrap_or_default()),
+                _ => None,
+            };
+            let newer = if replaceable {
+                tx.query_row(
+                    "SELECT id FROM event WHERE author = ?1 AND kind = ?2 AND created_at >= ?3 \
+                     LIMIT 1",
+                    rusqlite::params![author, kind, created_at],
+                    |_| Ok(()),
+                )
+                .optional()?
+            } else if let Some(d_tag) = &d_tag {
+                tx.query_row(
+                    "SELECT e.id FROM event e LEFT JOIN tag t ON e.id = t.event_id \
+                     WHERE e.author = ?1 AND e.kind = ?2 AND t.name = 'd' AND t.value = ?3 \
+                     AND e.created_at >= ?4 LIMIT 1",
+                    rusqlite::params![author, kind, d_tag, created_at],
+                    |_| Ok(()),
+                )
+                .optional()?
+            } else {
+                None
+            };
+            if newer.is_some() {
+                continue;
+            }
+            let inserted = tx.execute(
+                "INSERT OR IGNORE INTO event (event_hash, first_seen, created_at, expires_at, \
+                 author, kind, hidden, content) VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, ?7)",
+                rusqlite::params![
+                    event.id.as_bytes().as_slice(),
+                    first_seen,
+                    created_at,
+                    event.tags.expiration().map(|t| t.as_secs() as i64),
+                    author,
+                    kind,
+                    event.try_as_json()?,
+                ],
+            )?;
+            if inserted == 0 {
+                continue;
+            }
+            let event_id = tx.last_insert_rowid();
+            for tag in event.tags.iter() {
+                if let [name, value, ..] = tag.as_slice() {
+                    if name.len() == 1 {
+                        tx.execute(
+                            "INSERT INTO tag (event_id, name, value, created_at, kind) \
+                             VALUES (?1, ?2, ?3, ?4, ?5)",
+                            rusqlite::params![event_id, name, value, created_at, kind],
+                        )?;
+                    }
+                }
+            }
+            if replaceable {
+                tx.execute(
+                    "DELETE FROM event WHERE kind = ?1 AND author = ?2 AND id NOT IN \
+                     (SELECT id FROM event WHERE kind = ?1 AND author = ?2 \
+                     ORDER BY created_at DESC LIMIT 1)",
+                    rusqlite::params![kind, author],
+                )?;
+            } else if let Some(d_tag) = &d_tag {
+                tx.execute(
+                    "DELETE FROM event WHERE kind = ?1 AND author = ?2 AND id IN \
+                     (SELECT e.id FROM event e LEFT JOIN tag t ON e.id = t.event_id \
+                     WHERE e.kind = ?1 AND e.author = ?2 AND t.name = 'd' AND t.value = ?3 \
+                     ORDER BY e.created_at DESC LIMIT -1 OFFSET 1)",
+                    rusqlite::params![kind, author, d_tag],
+                )?;
+            } else if kind == 5 {
+                for id in event.tags.event_ids() {
+                    tx.execute(
+                        "UPDATE event SET hidden = 1 WHERE kind != 5 AND author = ?1 \
+                         AND event_hash = ?2",
+                        rusqlite::params![author, id.as_bytes().as_slice()],
+                    )?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Open the relay SQLite database, waiting [crate::Conf::sqlite_busy_timeout] when locked
+    fn open_db(&self, flags: OpenFlags) -> Result<Connection, Error> {
+        let conn = Connection::open_with_flags(self.db_path(), flags)?;
+        if let Some(timeout) = self.conf.sqlite_busy_timeout {
+            conn.busy_timeout(timeout)?;
+        }
+        Ok(conn)
+    }
+}
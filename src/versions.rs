@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+/// Version of the relay selected through cargo features
+#[cfg(feature = "relay_0_9_0")]
+pub const VERSION: &str = "0.9.0";
+
+/// Version of the relay selected through cargo features
+#[cfg(not(feature = "relay_0_9_0"))]
+pub const VERSION: &str = "N/A";
+
+/// Name of the vendored binary for the selected version
+#[cfg(feature = "relay_0_9_0")]
+const VENDORED_BINARY: Option<&str> = Some("nostr-rs-relay_0_9_0_linux");
+
+#[cfg(not(feature = "relay_0_9_0"))]
+const VENDORED_BINARY: Option<&str> = None;
+
+/// Returns the path of the vendored binary matching the selected version
+pub(crate) fn vendored_exe_path() -> Option<PathBuf> {
+    let name = VENDORED_BINARY?;
+    let mut bin_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    bin_dir.push("bin");
+    bin_dir.push(name);
+    Some(bin_dir)
+}
@@ -0,0 +1,29 @@
+use crate::{Error, NostrD};
+use std::{net::TcpStream, time::Duration};
+use tungstenite::{HandshakeError, Message};
+
+/// Time [NostrD::send_raw] waits for the relay to answer
+const RAW_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl NostrD {
+    /// Send `text` over a new WebSocket connection, returns the first text frame answered
+    ///
+    /// Meant for messages the nostr-sdk client can't express, e.g. an invalid `EVENT`.
+    pub fn send_raw(&self, text: &str) -> Result<String, Error> {
+        let stream = TcpStream::connect(self.socket_addr())?;
+        stream.set_read_timeout(Some(RAW_TIMEOUT))?;
+        let (mut socket, _) = tungstenite::client(self.ws_url(), stream).map_err(|e| match e {
+            HandshakeError::Failure(e) => e.into(),
+            // The read timeout elapsed during the handshake
+            HandshakeError::Interrupted(_) => Error::Timeout,
+        })?;
+        socket.send(Message::text(text))?;
+        loop {
+            // Pings are answered by tungstenite, a close frame ends with `ConnectionClosed`
+            if let Message::Text(answer) = socket.read()? {
+                let _ = socket.close(None);
+                return Ok(answer.to_string());
+            }
+        }
+    }
+}
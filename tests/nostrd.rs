@@ -1,13 +1,1171 @@
-use nostrd::NostrD;
+use nostrd::{Conf, Database, Error, NostrCluster, NostrD, PayToRelay};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    time::Duration,
+};
 
 fn new_nostrd_instance() -> NostrD {
-    std::env::set_var("RUST_LOG", "debug");
     let nostrd = NostrD::new().unwrap();
     println!("NostrD running at {}:{}", nostrd.addr, nostrd.port);
     nostrd
 }
 
+/// Fetch the relay NIP-11 document
+fn nip11(nostrd: &NostrD) -> String {
+    nostrd.wait_ws_ready(Duration::from_secs(1)).unwrap();
+    let mut stream = TcpStream::connect((nostrd.addr.as_str(), nostrd.port)).unwrap();
+    write!(
+        stream,
+        "GET / HTTP/1.1\r\nHost: {}\r\nAccept: application/nostr+json\r\nConnection: close\r\n\r\n",
+        nostrd.addr
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Write in `dir` a script running `command` before exec'ing the relay
+#[cfg(unix)]
+fn relay_wrapper(dir: &std::path::Path, command: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let relay = new_nostrd_instance().binary.clone();
+    let script = dir.join("relay.sh");
+    let content = format!("#!/bin/sh\n{}\nexec {} \"$@\"\n", command, relay.display());
+    std::fs::write(&script, content).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    script
+}
+
 #[test]
 fn simple_nostrd() {
     let _ = new_nostrd_instance();
 }
+
+#[test]
+fn spawn() {
+    let nostrd = NostrD::spawn().unwrap();
+    let pid = nostrd.pid();
+    // NostrD is Send
+    std::thread::spawn(move || assert_eq!(nostrd.pid(), pid))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn rust_log_unchanged() {
+    std::env::set_var("RUST_LOG", "nostrd_test=warn");
+    let _nostrd = NostrD::with_conf(&Conf::builder().log_level("info").build()).unwrap();
+    assert_eq!(std::env::var("RUST_LOG").unwrap(), "nostrd_test=warn");
+}
+
+#[test]
+fn envs() {
+    // RUST_LOG from envs takes precedence over Conf::log_level, hiding the ready marker
+    let conf = Conf::builder()
+        .env("RUST_LOG", "warn")
+        .attempts(1)
+        .start_timeout(Duration::from_millis(300))
+        .build();
+    assert!(matches!(
+        NostrD::with_conf(&conf),
+        Err(Error::SpawnFailed { .. })
+    ));
+}
+
+#[test]
+fn db_path() {
+    let nostrd = new_nostrd_instance();
+    assert!(!nostrd.is_persistent());
+    assert!(nostrd.db_path().is_file());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn new_async() {
+    let mut nostrd = NostrD::new_async().await.unwrap();
+    assert!(nostrd.is_alive());
+    TcpStream::connect((nostrd.addr.as_str(), nostrd.port)).unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn log_stream() {
+    use tokio_stream::StreamExt;
+
+    let nostrd = NostrD::new_async().await.unwrap();
+    let mut stream = Box::pin(nostrd.log_stream());
+    nip11(&nostrd);
+    let info_request = async {
+        while let Some(line) = stream.next().await {
+            if line.contains("server info request") {
+                break;
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(1), info_request)
+        .await
+        .unwrap();
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn client() {
+    let nostrd = new_nostrd_instance();
+    let client = nostrd.client().await.unwrap();
+    assert_eq!(client.relays().await.len(), 1);
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn publish() {
+    use nostr::{
+        event::{EventBuilder, Kind},
+        key::Keys,
+    };
+
+    let keys = Keys::generate();
+    let nostrd = new_nostrd_instance();
+    let note = EventBuilder::new(Kind::TextNote, "hello");
+    nostrd.publish(&keys, note).await.unwrap();
+
+    let conf = Conf::builder().event_kind_blacklist(vec![1]).build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let note = EventBuilder::new(Kind::TextNote, "hello");
+    assert!(matches!(
+        nostrd.publish(&keys, note).await,
+        Err(Error::Rejected(_))
+    ));
+    let note = EventBuilder::new(Kind::TextNote, "hello");
+    match nostrd.publish_outcome(&keys, note).await.unwrap() {
+        nostrd::PublishOutcome::Rejected { message } => assert!(message.starts_with("blocked:")),
+        outcome => panic!("expected a rejection, got {:?}", outcome),
+    }
+    let reaction = EventBuilder::new(Kind::Reaction, "+");
+    assert!(matches!(
+        nostrd.publish_outcome(&keys, reaction).await.unwrap(),
+        nostrd::PublishOutcome::Accepted(_)
+    ));
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn fetch() {
+    use nostr::{
+        event::{EventBuilder, Kind},
+        filter::Filter,
+        key::Keys,
+    };
+
+    let keys = Keys::generate();
+    let nostrd = new_nostrd_instance();
+    let timeout = Duration::from_secs(2);
+    let note = EventBuilder::new(Kind::TextNote, "hello");
+    let id = nostrd.publish(&keys, note).await.unwrap();
+    let reaction = EventBuilder::new(Kind::Reaction, "+");
+    nostrd.publish(&keys, reaction).await.unwrap();
+
+    let filters = vec![Filter::new().kind(Kind::TextNote)];
+    let events = nostrd.fetch(filters, timeout).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].id, id);
+
+    let filters = vec![
+        Filter::new().kind(Kind::TextNote),
+        Filter::new().kind(Kind::Reaction),
+    ];
+    assert_eq!(nostrd.fetch(filters, timeout).await.unwrap().len(), 2);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn event_count() {
+    let nostrd = new_nostrd_instance();
+    assert_eq!(nostrd.event_count().unwrap(), 0);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_busy_timeout() {
+    let timeout = Duration::from_millis(200);
+    let conf = Conf::builder().sqlite_busy_timeout(timeout).build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    let lock = rusqlite::Connection::open(nostrd.db_path()).unwrap();
+    lock.execute_batch("BEGIN IMMEDIATE;").unwrap();
+    let start = std::time::Instant::now();
+    assert!(matches!(nostrd.clear_events(), Err(Error::Sqlite(_))));
+    assert!(start.elapsed() >= timeout);
+    // Readers are not blocked by the writer
+    assert_eq!(nostrd.event_count().unwrap(), 0);
+}
+
+#[cfg(all(feature = "sqlite", feature = "client"))]
+#[tokio::test]
+async fn clear_events() {
+    use nostr::{
+        event::{EventBuilder, Kind},
+        key::Keys,
+    };
+
+    let mut nostrd = new_nostrd_instance();
+    let note = EventBuilder::new(Kind::TextNote, "hello");
+    nostrd.publish(&Keys::generate(), note).await.unwrap();
+    assert_eq!(nostrd.event_count().unwrap(), 1);
+    nostrd.clear_events().unwrap();
+    assert_eq!(nostrd.event_count().unwrap(), 0);
+}
+
+#[cfg(all(feature = "sqlite", feature = "client"))]
+#[tokio::test]
+async fn seed_events() {
+    use nostr::{
+        event::{EventBuilder, FinalizeEvent, Kind, Tag},
+        filter::Filter,
+        key::Keys,
+    };
+
+    let mut nostrd = new_nostrd_instance();
+    let note = EventBuilder::new(Kind::TextNote, "hello")
+        .tags(vec![Tag::parse(["t", "nostrd"]).unwrap()])
+        .finalize(&Keys::generate())
+        .unwrap();
+    nostrd.seed_events(&[note.clone(), note.clone()]).unwrap();
+    assert_eq!(nostrd.event_count().unwrap(), 1);
+
+    let client = nostrd.client().await.unwrap();
+    let events = client
+        .fetch_events(Filter::new().hashtag("nostrd"))
+        .await
+        .unwrap();
+    assert!(events.contains(&note));
+}
+
+#[cfg(all(feature = "sqlite", feature = "client"))]
+#[tokio::test]
+async fn seed_replaceable_events() {
+    use nostr::{
+        event::{EventBuilder, FinalizeEvent, Kind, Tag},
+        filter::Filter,
+        key::Keys,
+        types::Timestamp,
+    };
+
+    let keys = Keys::generate();
+    let at = |secs: u64| Timestamp::from_secs(1_700_000_000 + secs);
+    let metadata = |secs: u64| {
+        EventBuilder::new(Kind::Metadata, format!("{{\"name\":\"{}\"}}", secs))
+            .custom_created_at(at(secs))
+            .finalize(&keys)
+            .unwrap()
+    };
+    let mut nostrd = new_nostrd_instance();
+    // The older version is deleted, then not stored as a newer one is
+    nostrd.seed_events(&[metadata(1), metadata(2)]).unwrap();
+    nostrd.seed_events(&[metadata(0)]).unwrap();
+    assert_eq!(nostrd.event_count().unwrap(), 1);
+    let client = nostrd.client().await.unwrap();
+    let events = client
+        .fetch_events(Filter::new().kind(Kind::Metadata))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert!(events.contains(&metadata(2)));
+
+    // Parameterized replaceable events are replaced per `d` tag
+    let list = |secs, d: &str| {
+        EventBuilder::new(Kind::Custom(30000), "")
+            .tags(vec![Tag::identifier(d)])
+            .custom_created_at(at(secs))
+            .finalize(&keys)
+            .unwrap()
+    };
+    let lists = [list(1, "a"), list(2, "a"), list(1, "b")];
+    nostrd.seed_events(&lists).unwrap();
+    assert_eq!(nostrd.event_count().unwrap(), 3);
+
+    // A deletion hides the events it targets
+    let note = EventBuilder::new(Kind::TextNote, "hello")
+        .finalize(&keys)
+        .unwrap();
+    let deletion = EventBuilder::new(Kind::EventDeletion, "")
+        .tags(vec![Tag::event(note.id)])
+        .finalize(&keys)
+        .unwrap();
+    nostrd.seed_events(&[note, deletion]).unwrap();
+    let events = client
+        .fetch_events(Filter::new().kind(Kind::TextNote))
+        .await
+        .unwrap();
+    assert!(events.is_empty());
+}
+
+#[test]
+fn missing_binary() {
+    let mut conf = Conf::default();
+    conf.binary = Some("path/to/nowhere".into());
+    match NostrD::with_conf(&conf) {
+        Err(Error::BinaryNotFound(path)) => assert_eq!(path, PathBuf::from("path/to/nowhere")),
+        _ => panic!("expected Error::BinaryNotFound"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn binary_not_executable() {
+    let dir = tempfile::tempdir().unwrap();
+    let binary = dir.path().join("nostr-rs-relay");
+    std::fs::write(&binary, "").unwrap();
+    let conf = Conf::builder().binary(binary.to_str().unwrap()).build();
+    match NostrD::with_conf(&conf) {
+        Err(Error::BinaryNotExecutable(path)) => assert_eq!(path, binary),
+        _ => panic!("expected Error::BinaryNotExecutable"),
+    }
+}
+
+#[test]
+fn error_display() {
+    let e = Error::BinaryNotFound("path/to/nowhere".into());
+    assert_eq!(e.to_string(), "relay binary not found at path/to/nowhere");
+    let e = Error::SpawnFailed { attempts: 2 };
+    assert_eq!(e.to_string(), "relay failed to start after 2 attempt(s)");
+}
+
+#[test]
+fn validate() {
+    assert!(Conf::default().validate().is_ok());
+    for conf in [
+        Conf::builder().ip("not an ip").build(),
+        Conf::builder().config_toml("[network").build(),
+        Conf::builder().max_buffered_logs(0).build(),
+    ] {
+        assert!(matches!(conf.validate(), Err(Error::InvalidConfig(_))));
+        assert!(matches!(
+            NostrD::with_conf(&conf),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
+
+#[test]
+fn reserved_args() {
+    for arg in ["--db", "--config=relay.toml", "-d"] {
+        let conf = Conf::builder().args([arg, "/tmp"]).build();
+        match NostrD::with_conf(&conf) {
+            Err(Error::InvalidConfig(e)) => assert!(e.contains("managed by nostrd")),
+            _ => panic!("expected Error::InvalidConfig"),
+        }
+    }
+    // Only the exact flags are reserved, not values starting like them
+    let conf = Conf::builder().args(["--name", "-dev", "-cfg"]).build();
+    conf.validate().unwrap();
+}
+
+#[test]
+fn conf_builder() {
+    let conf = Conf::builder().ip("127.0.0.1").attempts(2).build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert_eq!(nostrd.addr, "127.0.0.1");
+}
+
+#[test]
+fn effective_conf() {
+    let conf = Conf::builder().relay_name("nostrd").build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let mut effective = nostrd.effective_conf();
+    assert_eq!(effective.ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(effective.port, Some(nostrd.port));
+    assert_eq!(effective.relay_name, conf.relay_name);
+
+    // Doesn't refer to the first relay work directory
+    assert_eq!(effective.current_dir, None);
+    drop(nostrd);
+    effective.port = None;
+    let mut other = NostrD::with_conf(&effective).unwrap();
+    assert!(other.is_alive());
+}
+
+#[test]
+fn get_available_port_on() {
+    assert_ne!(nostrd::get_available_port_on("127.0.0.1").unwrap(), 0);
+    assert!(matches!(
+        nostrd::get_available_port_on("not an ip"),
+        Err(Error::Io(_))
+    ));
+}
+
+#[test]
+fn port_zero() {
+    let conf = Conf::builder().port(0).build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert_ne!(nostrd.port, 0);
+    assert_eq!(nostrd.effective_conf().port, Some(nostrd.port));
+    TcpStream::connect(nostrd.socket_addr()).unwrap();
+}
+
+#[test]
+fn port_in_use() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let conf = Conf::builder().port(port).build();
+    match NostrD::with_conf(&conf) {
+        Err(Error::PortInUse(p)) => assert_eq!(p, port),
+        _ => panic!("expected Error::PortInUse"),
+    }
+}
+
+#[test]
+fn get_available_ports() {
+    let mut ports = nostrd::get_available_ports(8).unwrap();
+    ports.sort_unstable();
+    ports.dedup();
+    assert_eq!(ports.len(), 8);
+}
+
+#[test]
+fn cluster() {
+    let cluster = NostrCluster::new(3, &Conf::default()).unwrap();
+    let mut urls = cluster.urls();
+    urls.sort_unstable();
+    urls.dedup();
+    assert_eq!(urls.len(), 3);
+    for nostrd in &cluster.nodes {
+        TcpStream::connect((nostrd.addr.as_str(), nostrd.port)).unwrap();
+    }
+}
+
+#[test]
+fn parallel_spawn() {
+    let handles: Vec<_> = (0..8)
+        .map(|_| std::thread::spawn(|| NostrD::new().unwrap()))
+        .collect();
+    let nodes: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    let mut ports: Vec<_> = nodes.iter().map(|n| n.port).collect();
+    ports.sort_unstable();
+    ports.dedup();
+    assert_eq!(ports.len(), 8);
+    for nostrd in &nodes {
+        nostrd.wait_ws_ready(Duration::from_secs(1)).unwrap();
+    }
+}
+
+#[test]
+fn zero_attempts() {
+    let mut conf = Conf::default();
+    conf.attempts = 0;
+    NostrD::with_conf(&conf).unwrap();
+
+    let conf = Conf::builder()
+        .attempts(0)
+        .ready_marker("never logged")
+        .start_timeout(Duration::from_millis(100))
+        .build();
+    assert!(matches!(
+        NostrD::with_conf(&conf),
+        Err(Error::SpawnFailed { attempts: 1 })
+    ));
+}
+
+#[test]
+fn retry_backoff() {
+    let conf = Conf::builder()
+        .attempts(3)
+        .retry_backoff(Duration::from_millis(200))
+        .ready_marker("never logged")
+        .start_timeout(Duration::from_millis(100))
+        .build();
+    let start = std::time::Instant::now();
+    assert!(matches!(
+        NostrD::with_conf(&conf),
+        Err(Error::SpawnFailed { attempts: 3 })
+    ));
+    // 3 start timeouts & 2 backoffs
+    assert!(start.elapsed() >= Duration::from_millis(3 * 100 + 2 * 200));
+}
+
+#[test]
+fn startup_failed() {
+    // The relay panics on startup
+    let conf = Conf::builder()
+        .config_toml("[database]\nmin_conn = 8\nmax_conn = 2\n")
+        .attempts(3)
+        .build();
+    let start = std::time::Instant::now();
+    match NostrD::with_conf(&conf) {
+        Err(Error::StartupFailed(line)) => {
+            assert!(line.contains("panicked at"), "{}", line);
+            assert!(line.contains("cannot exceed max_conn"), "{}", line);
+        }
+        _ => panic!("expected Error::StartupFailed"),
+    }
+    // Not retried until the start timeout
+    assert!(start.elapsed() < conf.start_timeout);
+}
+
+#[cfg(unix)]
+#[test]
+fn startup_failed_database() {
+    // A directory in place of the database file, the relay logs the SQLite error
+    let dir = tempfile::tempdir().unwrap();
+    let script = relay_wrapper(dir.path(), "mkdir \"$4/nostr.db\"");
+    let conf = Conf::builder().binary(script.to_str().unwrap()).build();
+    match NostrD::with_conf(&conf) {
+        Err(Error::StartupFailed(line)) => {
+            assert!(line.contains("unable to open database file"), "{}", line)
+        }
+        _ => panic!("expected Error::StartupFailed"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn attempts_used() {
+    let nostrd = new_nostrd_instance();
+    assert_eq!(nostrd.attempts_used(), 1);
+    assert!(!nostrd.port_changed());
+    // The first attempt fails, the retry picks another port
+    let dir = tempfile::tempdir().unwrap();
+    let failed = dir.path().join("failed");
+    let command = format!("[ -e {0} ] || {{ touch {0}; exit 1; }}", failed.display());
+    let script = relay_wrapper(dir.path(), &command);
+    let conf = Conf::builder()
+        .binary(script.to_str().unwrap())
+        .attempts(2)
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert_eq!(nostrd.attempts_used(), 2);
+    assert!(nostrd.port_changed());
+    assert_eq!(nostrd.effective_conf().port, Some(nostrd.port));
+    let config = std::fs::read_to_string(nostrd.config_path()).unwrap();
+    assert!(config.contains(&format!("port = {}", nostrd.port)));
+    TcpStream::connect(nostrd.socket_addr()).unwrap();
+    // A fixed port is kept
+    std::fs::remove_file(&failed).unwrap();
+    let port = nostrd::get_available_port().unwrap();
+    let conf = Conf::builder()
+        .binary(script.to_str().unwrap())
+        .attempts(2)
+        .port(port)
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert_eq!(nostrd.attempts_used(), 2);
+    assert!(!nostrd.port_changed());
+    assert_eq!(nostrd.port, port);
+}
+
+#[cfg(unix)]
+#[test]
+fn squatted_port() {
+    // Another relay takes the picked port first, the first attempt fails to bind it
+    let dir = tempfile::tempdir().unwrap();
+    let squat = dir.path().join("squat");
+    let command = format!(
+        "[ -e {0} ] || {{ mkdir {0}; {1} --config \"$2\" --db {0} > {0}/log 2>&1 & echo $! > {0}/pid; \
+         until grep -q '{2}' {0}/log; do sleep 0.1; done; sleep 0.1; }}",
+        squat.display(),
+        new_nostrd_instance().binary.display(),
+        nostrd::READY_MARKER,
+    );
+    let script = relay_wrapper(dir.path(), &command);
+    let conf = Conf::builder()
+        .binary(script.to_str().unwrap())
+        .attempts(2)
+        .build();
+    let nostrd = NostrD::with_conf(&conf);
+    let pid = std::fs::read_to_string(squat.join("pid")).unwrap();
+    std::process::Command::new("kill")
+        .arg(pid.trim())
+        .status()
+        .unwrap();
+    let mut nostrd = nostrd.unwrap();
+    assert_eq!(nostrd.attempts_used(), 2);
+    assert!(nostrd.port_changed());
+    std::thread::sleep(Duration::from_millis(500));
+    assert!(nostrd.is_alive());
+}
+
+#[test]
+fn keep_data_dir() {
+    let conf = Conf::builder().keep_data_dir(true).build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert!(nostrd.is_persistent());
+    let workdir = nostrd.workdir();
+    drop(nostrd);
+    assert!(workdir.join("config.toml").exists());
+    std::fs::remove_dir_all(workdir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn current_dir() {
+    // The relay runs in its work directory, `pwd` is logged by the wrapper script
+    let dir = tempfile::tempdir().unwrap();
+    let script = relay_wrapper(dir.path(), "pwd");
+
+    let conf = Conf::builder().binary(script.to_str().unwrap()).build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    let pwd = nostrd.drain_logs().remove(0);
+    assert_eq!(PathBuf::from(pwd.trim_end()), nostrd.workdir());
+
+    let conf = Conf::builder()
+        .binary(script.to_str().unwrap())
+        .current_dir(dir.path())
+        .build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    let pwd = nostrd.drain_logs().remove(0);
+    assert_eq!(PathBuf::from(pwd.trim_end()), dir.path());
+    assert_eq!(
+        nostrd.effective_conf().current_dir.as_deref(),
+        Some(dir.path())
+    );
+}
+
+#[test]
+fn data_dir_parent() {
+    let parent = tempfile::tempdir().unwrap();
+    let conf = Conf::builder().data_dir_parent(parent.path()).build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert_eq!(nostrd.workdir().parent(), Some(parent.path()));
+    assert!(nostrd.db_path().exists());
+}
+
+#[test]
+fn wait_for_log() {
+    let mut nostrd = new_nostrd_instance();
+    nostrd.kill().unwrap();
+    // The startup lines are still buffered
+    nostrd
+        .wait_for_log("Starting up", Duration::from_secs(1))
+        .unwrap();
+    assert!(matches!(
+        nostrd.wait_for_log("never logged", Duration::from_millis(200)),
+        Err(Error::Timeout)
+    ));
+}
+
+#[test]
+fn parse_log_line() {
+    let line = "\u{1b}[2m2026-10-14T12:58:07.755749Z\u{1b}[0m \u{1b}[32m INFO\u{1b}[0m \
+        \u{1b}[2mnostr_rs_relay::server\u{1b}[0m\u{1b}[2m:\u{1b}[0m control message listener started\n";
+    let record = NostrD::parse_log_line(line).unwrap();
+    assert_eq!(record.timestamp, "2026-10-14T12:58:07.755749Z");
+    assert_eq!(record.level, log::Level::Info);
+    assert_eq!(record.target, "nostr_rs_relay::server");
+    assert_eq!(record.message, "control message listener started");
+    assert_eq!(NostrD::parse_log_line("not a log line"), None);
+
+    let mut nostrd = new_nostrd_instance();
+    let record = nostrd
+        .wait_for_level(log::Level::Info, Duration::from_secs(1))
+        .unwrap();
+    assert!(record.target.starts_with("nostr_rs_relay"));
+    assert!(matches!(
+        nostrd.wait_for_level(log::Level::Error, Duration::from_millis(200)),
+        Err(Error::Timeout)
+    ));
+}
+
+#[test]
+fn wait_for_connection() {
+    let nostrd = new_nostrd_instance();
+    assert!(matches!(
+        nostrd.wait_for_connection(Duration::from_millis(200)),
+        Err(Error::Timeout)
+    ));
+    // Websocket handshake, the connection is kept open until the end of the test
+    let mut stream = TcpStream::connect(nostrd.socket_addr()).unwrap();
+    write!(
+        stream,
+        "GET / HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+        Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        nostrd.addr
+    )
+    .unwrap();
+    nostrd.wait_for_connection(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn log_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("relay.log");
+    let conf = Conf::builder().log_file(&path).build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    let logs = std::fs::read_to_string(&path).unwrap();
+    assert!(logs.contains(nostrd::READY_MARKER));
+    // Appended to on restart
+    nostrd.restart().unwrap();
+    let logs = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(logs.matches(nostrd::READY_MARKER).count(), 2);
+}
+
+#[test]
+fn max_buffered_logs() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("relay.log");
+    let conf = Conf::builder().log_file(&path).max_buffered_logs(3).build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    nostrd.kill().unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    // Only the most recent lines are kept
+    let logs: Vec<_> = nostrd.logs_iter().collect();
+    let file = std::fs::read_to_string(&path).unwrap();
+    let file: Vec<_> = file.lines().collect();
+    assert!(file.len() > 3);
+    assert_eq!(logs.len(), 3);
+    assert_eq!(logs.last().unwrap().trim_end(), *file.last().unwrap());
+}
+
+#[test]
+fn next_log() {
+    let nostrd = new_nostrd_instance();
+    assert!(nostrd.next_log(Duration::from_secs(1)).unwrap().is_some());
+    // Returns None once the relay stays quiet
+    while nostrd
+        .next_log(Duration::from_millis(200))
+        .unwrap()
+        .is_some()
+    {}
+}
+
+#[test]
+fn readers_exit() {
+    let mut nostrd = new_nostrd_instance();
+    nostrd.kill().unwrap();
+    // The reader threads stop on EOF, dropping their senders
+    loop {
+        match nostrd.next_log(Duration::from_secs(1)) {
+            Ok(Some(_)) => {}
+            Ok(None) => panic!("reader threads still running"),
+            Err(e) => break assert!(matches!(e, Error::LogsDisconnected)),
+        }
+    }
+}
+
+#[test]
+fn wait_ws_ready() {
+    let mut nostrd = new_nostrd_instance();
+    nostrd.wait_ws_ready(Duration::from_secs(1)).unwrap();
+    nostrd.kill().unwrap();
+    assert!(matches!(
+        nostrd.wait_ws_ready(Duration::from_millis(200)),
+        Err(Error::Timeout)
+    ));
+}
+
+#[test]
+fn restart() {
+    let mut nostrd = new_nostrd_instance();
+    let url = nostrd.url();
+    let pid = nostrd.pid();
+    nostrd.restart().unwrap();
+    assert_eq!(nostrd.url(), url);
+    assert_eq!(nostrd.http_url(), url.replacen("ws", "http", 1));
+    assert_ne!(nostrd.pid(), pid);
+    TcpStream::connect((nostrd.addr.as_str(), nostrd.port)).unwrap();
+}
+
+#[test]
+fn socket_addr() {
+    let nostrd = new_nostrd_instance();
+    let addr = nostrd.socket_addr();
+    assert_eq!(addr.to_string(), format!("{}:{}", nostrd.addr, nostrd.port));
+    TcpStream::connect(addr).unwrap();
+}
+
+#[test]
+fn tls() {
+    let nostrd = new_nostrd_instance();
+    let address = format!("{}:{}", nostrd.addr, nostrd.port);
+    assert_eq!(nostrd.url(), format!("ws://{}", address));
+    assert_eq!(nostrd.ws_url(), format!("ws://{}", address));
+    assert_eq!(nostrd.wss_url(), format!("wss://{}", address));
+
+    let conf = Conf::builder().tls(true).build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert_eq!(nostrd.url(), nostrd.wss_url());
+}
+
+#[test]
+fn startup_logs() {
+    let mut nostrd = new_nostrd_instance();
+    nostrd
+        .wait_for_log(nostrd::READY_MARKER, Duration::from_secs(1))
+        .unwrap();
+}
+
+#[test]
+fn drain_logs() {
+    let mut nostrd = new_nostrd_instance();
+    nostrd.kill().unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!nostrd.drain_logs().is_empty());
+    assert!(nostrd.drain_logs().is_empty());
+}
+
+#[test]
+fn logs_iter() {
+    let mut nostrd = new_nostrd_instance();
+    assert!(nostrd
+        .logs_iter()
+        .any(|line| line.contains(nostrd::READY_MARKER)));
+    nostrd.kill().unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    nostrd.logs_iter().for_each(drop);
+    assert_eq!(nostrd.logs_iter().count(), 0);
+}
+
+#[test]
+fn log_source() {
+    let mut nostrd = new_nostrd_instance();
+    nostrd.kill().unwrap();
+    let timeout = Duration::from_secs(1);
+    loop {
+        let (source, line) = nostrd.logs.recv_timeout_tagged(timeout).unwrap();
+        if line.contains(nostrd::READY_MARKER) {
+            assert_eq!(source, nostrd::LogSource::Stdout);
+            break;
+        }
+    }
+}
+
+#[test]
+fn is_alive() {
+    let mut nostrd = new_nostrd_instance();
+    assert!(nostrd.is_alive());
+    nostrd.kill().unwrap();
+    assert!(!nostrd.is_alive());
+}
+
+#[test]
+fn startup_duration() {
+    let nostrd = new_nostrd_instance();
+    let duration = nostrd.startup_duration();
+    assert!(duration > Duration::ZERO);
+    assert!(duration < Conf::default().start_timeout);
+}
+
+#[test]
+fn relay_version() {
+    let nostrd = new_nostrd_instance();
+    assert_eq!(nostrd.relay_version().unwrap(), nostrd::VERSION);
+    // Cached
+    assert_eq!(nostrd.relay_version().unwrap(), nostrd::VERSION);
+}
+
+#[test]
+fn exit_status() {
+    let mut nostrd = new_nostrd_instance();
+    assert!(nostrd.kill().unwrap().success());
+    // Already exited, the status is returned again
+    assert!(nostrd.kill().unwrap().success());
+}
+
+#[cfg(unix)]
+#[test]
+fn shutdown_timeout() {
+    let conf = Conf::builder()
+        .shutdown_timeout(Duration::from_millis(200))
+        .build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    // A stopped process won't handle SIGINT
+    std::process::Command::new("kill")
+        .args(["-STOP", &nostrd.pid().to_string()])
+        .status()
+        .unwrap();
+    assert!(!nostrd.kill().unwrap().success());
+    assert!(!nostrd.is_alive());
+}
+
+#[cfg(unix)]
+#[test]
+fn stop_signal() {
+    use nostrd::Signal;
+    use std::os::unix::process::ExitStatusExt;
+    let conf = Conf::builder().stop_signal(Signal::SIGKILL).build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    assert_eq!(
+        nostrd.kill().unwrap().signal(),
+        Some(Signal::SIGKILL as i32)
+    );
+    let conf = Conf::builder().stop_signal(Signal::SIGTERM).build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    // Handled as gracefully as SIGINT
+    assert!(nostrd.kill().unwrap().success());
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_logs() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = relay_wrapper(dir.path(), "printf 'stray \\377 byte\\n'");
+
+    let conf = Conf::builder().binary(script.to_str().unwrap()).build();
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    let line = nostrd.drain_logs().remove(0);
+    assert_eq!(line, "stray \u{FFFD} byte\n");
+}
+
+#[test]
+fn config_toml() {
+    let conf = Conf::builder()
+        .config_toml("[info]\nname = \"nostrd\"\n\n[network]\nport = 1\n")
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let config = std::fs::read_to_string(nostrd.config_path()).unwrap();
+    assert!(config.contains("name = \"nostrd\""));
+    assert!(config.contains(&format!("port = {}", nostrd.port)));
+    // unset sections are not written
+    assert!(!config.contains("[limits]"));
+}
+
+#[test]
+fn render_config() {
+    let conf = Conf::builder().relay_name("nostrd").build();
+    let config = conf.render_config("127.0.0.1", 1234).unwrap();
+    assert!(config.contains("[network]\naddress = \"127.0.0.1\"\nport = 1234\n"));
+    assert!(config.contains("name = \"nostrd\""));
+    // Same as the file the relay is started with
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(nostrd.config_path()).unwrap(),
+        conf.render_config(&nostrd.addr, nostrd.port).unwrap()
+    );
+    let conf = Conf::builder().config_toml("not toml").build();
+    assert!(matches!(
+        conf.render_config("127.0.0.1", 1234),
+        Err(Error::InvalidConfig(_))
+    ));
+}
+
+/// A [Database::Postgres] conf for `postgres` on localhost
+fn postgres_conf() -> Conf {
+    Conf::builder()
+        .database(Database::Postgres {
+            connection: "postgresql://postgres@localhost:5432/nostr".into(),
+        })
+        .build()
+}
+
+#[test]
+fn postgres_config() {
+    let config = postgres_conf().render_config("127.0.0.1", 1234).unwrap();
+    assert!(
+        config.contains("[database]\nconnection = \"postgresql://postgres@localhost:5432/nostr\"")
+    );
+    assert!(config.contains("engine = \"postgres\""));
+}
+
+#[cfg(unix)]
+#[test]
+fn postgres() {
+    // The wrapper logs its arguments, then runs the relay on SQLite as no server is available
+    let dir = tempfile::tempdir().unwrap();
+    let script = relay_wrapper(
+        dir.path(),
+        "echo \"args: $*\"; grep -v -e '^engine' -e '^connection' \"$2\" > \"$2.tmp\"; mv \"$2.tmp\" \"$2\"",
+    );
+    let mut conf = postgres_conf();
+    conf.binary = Some(script.to_str().unwrap().into());
+    let mut nostrd = NostrD::with_conf(&conf).unwrap();
+    let args = nostrd.drain_logs().remove(0);
+    assert!(args.starts_with("args: --config "), "{}", args);
+    assert!(!args.contains("--db"), "{}", args);
+    assert!(nostrd.is_persistent());
+}
+
+#[test]
+fn from_toml_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, "[info]\nname = \"from file\"\n").unwrap();
+    let conf = Conf::from_toml_path(&path).unwrap();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert!(nip11(&nostrd).contains("\"name\": \"from file\""));
+
+    std::fs::write(&path, "[info").unwrap();
+    assert!(matches!(
+        Conf::from_toml_path(&path),
+        Err(Error::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn relay_name() {
+    let conf = Conf::builder().relay_name("nostrd relay").build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert!(nip11(&nostrd).contains("\"name\": \"nostrd relay\""));
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn nip11_document() {
+    let conf = Conf::builder().relay_name("nostrd relay").build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let nip11 = nostrd.nip11().unwrap();
+    assert_eq!(nip11["name"], "nostrd relay");
+    assert_eq!(nip11["version"], nostrd::VERSION);
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn health() {
+    let mut nostrd = new_nostrd_instance();
+    assert!(nostrd.health().unwrap());
+    nostrd.kill().unwrap();
+    assert!(matches!(nostrd.health(), Err(Error::Http(_))));
+}
+
+#[cfg(feature = "ws")]
+#[test]
+fn send_raw() {
+    let nostrd = new_nostrd_instance();
+    let answer = nostrd.send_raw(r#"["REQ", "sub", {}]"#).unwrap();
+    assert_eq!(answer, r#"["EOSE","sub"]"#);
+    // Malformed messages are answered with a notice
+    let answer = nostrd.send_raw(r#"["EVENT", {"id": "invalid"}]"#).unwrap();
+    assert!(answer.starts_with(r#"["NOTICE""#), "{}", answer);
+}
+
+#[test]
+fn relay_description() {
+    let conf = Conf::builder()
+        .relay_description("a \"quoted\"\nrelay")
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    assert!(nip11(&nostrd).contains(r#""description": "a \"quoted\"\nrelay""#));
+}
+
+#[test]
+fn info_pubkey() {
+    let pubkey = "a".repeat(64);
+    let conf = Conf::builder()
+        .info_pubkey(pubkey.clone())
+        .info_contact("mailto:nostrd@example.com")
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let nip11 = nip11(&nostrd);
+    assert!(nip11.contains(&pubkey));
+    assert!(nip11.contains("mailto:nostrd@example.com"));
+
+    let conf = Conf::builder().info_pubkey("not hex").build();
+    assert!(matches!(
+        NostrD::with_conf(&conf),
+        Err(Error::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn limits() {
+    let conf = Conf::builder()
+        .max_event_bytes(1024)
+        .messages_per_sec(2)
+        .subscriptions_per_min(3)
+        .max_ws_message_bytes(2048)
+        .max_ws_frame_bytes(512)
+        .db_conns_per_client(2)
+        .event_persist_buffer(16)
+        .broadcast_buffer(32)
+        .max_blocking_threads(4)
+        .max_connections(2)
+        .event_kind_blacklist(vec![4])
+        .event_kind_allowlist(vec![0, 1])
+        .reject_future_seconds(60)
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let config = std::fs::read_to_string(nostrd.config_path()).unwrap();
+    assert!(config.contains("[limits]\n"));
+    assert!(config.contains("max_event_bytes = 1024\n"));
+    assert!(config.contains("messages_per_sec = 2\n"));
+    assert!(config.contains("subscriptions_per_min = 3\n"));
+    assert!(config.contains("max_ws_message_bytes = 2048\n"));
+    assert!(config.contains("max_ws_frame_bytes = 512\n"));
+    assert!(config.contains("db_conns_per_client = 2\n"));
+    assert!(config.contains("event_persist_buffer = 16\n"));
+    assert!(config.contains("broadcast_buffer = 32\n"));
+    assert!(config.contains("max_blocking_threads = 4\n"));
+    assert!(config.contains("[database]\nmax_conn = 2\nmin_conn = 2\n"));
+    assert_eq!(config.matches("[limits]").count(), 1);
+    assert!(config.contains("event_kind_blacklist = [4]\n"));
+    assert!(config.contains("event_kind_allowlist = [0, 1]\n"));
+    assert!(config.contains("[options]\nreject_future_seconds = 60\n"));
+}
+
+#[test]
+fn network() {
+    let conf = Conf::builder()
+        .ping_interval_seconds(1)
+        .remote_ip_header("x-forwarded-for")
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let config = std::fs::read_to_string(nostrd.config_path()).unwrap();
+    assert!(config.contains("ping_interval_seconds = 1\n"));
+    assert!(config.contains("remote_ip_header = \"x-forwarded-for\"\n"));
+}
+
+#[test]
+fn pay_to_relay() {
+    let mut pay = PayToRelay {
+        enabled: true,
+        admission_cost: 10,
+        cost_per_event: 1,
+        terms_message: Some("pay up".into()),
+        node_url: "http://127.0.0.1:1".into(),
+        api_secret: "secret".into(),
+    };
+    let conf = Conf::builder().pay_to_relay(pay.clone()).build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let config = std::fs::read_to_string(nostrd.config_path()).unwrap();
+    assert!(config.contains("[pay_to_relay]\n"));
+    assert!(config.contains("admission_cost = 10\n"));
+    assert!(config.contains("terms_message = \"pay up\"\n"));
+
+    pay.api_secret.clear();
+    let conf = Conf::builder().pay_to_relay(pay).build();
+    assert!(matches!(conf.validate(), Err(Error::InvalidConfig(_))));
+}
+
+#[test]
+fn verified_users() {
+    let conf = Conf::builder()
+        .verified_users_mode("enabled")
+        .verified_users_domain_whitelist(vec!["example.com"])
+        .verified_users_domain_blacklist(vec!["example.org"])
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let config = std::fs::read_to_string(nostrd.config_path()).unwrap();
+    assert!(config.contains("[verified_users]\n"));
+    assert!(config.contains("mode = \"enabled\"\n"));
+    assert!(config.contains("domain_whitelist = [\"example.com\"]\n"));
+    assert!(config.contains("domain_blacklist = [\"example.org\"]\n"));
+}
+
+#[test]
+fn authorization() {
+    let pubkey = "b".repeat(64);
+    let conf = Conf::builder()
+        .pubkey_whitelist(vec![pubkey.clone()])
+        .nip42_auth(true)
+        .build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let config = std::fs::read_to_string(nostrd.config_path()).unwrap();
+    assert!(config.contains("[authorization]\nnip42_auth = true\n"));
+    assert!(config.contains(&format!("pubkey_whitelist = [\"{}\"]", pubkey)));
+
+    let conf = Conf::builder()
+        .pubkey_whitelist(vec![pubkey, "c".repeat(63)])
+        .build();
+    assert!(matches!(
+        NostrD::with_conf(&conf),
+        Err(Error::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn diagnostics() {
+    // Enabling it binds the tokio-console port, keep the tests independent of it
+    let conf = Conf::builder().diagnostics_tracing(false).build();
+    let nostrd = NostrD::with_conf(&conf).unwrap();
+    let config = std::fs::read_to_string(nostrd.config_path()).unwrap();
+    assert!(config.contains("[diagnostics]\ntracing = false\n"));
+}